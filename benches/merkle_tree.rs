@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use forrest::hasher::Hash32;
+use forrest::merkle_tree::MerkleTree;
+use sha3::{Digest, Sha3_256};
+
+const DEPTH: u32 = 20;
+const INITIAL_LEAF: &str = "0xabababababababababababababababababababababababababababababababab";
+
+// The previous layout of the tree where every node was its own heap allocated Vec<u8>, kept here as a
+// baseline so the benchmark shows what the flat buffer of fixed size hashes gains over it. It counted the
+// leaves as a layer of their own, so it has to be one level deeper to hold the same number of leaves
+mod vec_of_vecs {
+    use super::*;
+
+    fn hash(v: Vec<u8>) -> Vec<u8> {
+        let mut hasher = Sha3_256::new();
+        hasher.update(v);
+        hasher.finalize().to_vec()
+    }
+
+    fn concatenate_hashes(left: Vec<u8>, right: Vec<u8>) -> Vec<u8> {
+        let mut concatenation = left;
+        let mut right_vec = right;
+        concatenation.append(&mut right_vec);
+        concatenation
+    }
+
+    pub fn new(depth: u32, initial_leaf: &str) -> Vec<Vec<u8>> {
+        let base: u32 = 2;
+        let mut representation = vec![Vec::new(); base.pow(depth) as usize];
+        let as_bytes = hex::decode(initial_leaf.trim_start_matches("0x")).unwrap();
+
+        let start_of_nodes_at_depth = base.pow(depth - 1) as usize;
+        for node in representation.iter_mut().skip(start_of_nodes_at_depth) {
+            *node = as_bytes.clone()
+        }
+
+        let mut current_depth = depth - 1;
+        while current_depth > 0 {
+            let start_of_nodes_at_depth = base.pow(current_depth - 1) as usize;
+            let end_of_nodes_at_depth = base.pow(current_depth) as usize;
+            let mut seen_hashes: HashMap<String, Vec<u8>> = HashMap::new();
+            for i in start_of_nodes_at_depth..end_of_nodes_at_depth {
+                let concatenation = concatenate_hashes(
+                    representation[2 * i].clone(),
+                    representation[2 * i + 1].clone(),
+                );
+                let hex_concatenation = hex::encode(concatenation.clone());
+                let hashed = if let Some(hash) = seen_hashes.get(hex_concatenation.as_str()) {
+                    hash.to_vec()
+                } else {
+                    let hashed = hash(concatenation);
+                    seen_hashes.insert(hex_concatenation, hashed.clone());
+                    hashed
+                };
+                representation[i] = hashed;
+            }
+            current_depth -= 1;
+        }
+        representation
+    }
+
+    pub fn set(representation: &mut [Vec<u8>], index: usize, value: Vec<u8>) {
+        representation[index] = value;
+        let mut current = index;
+        while current > 1 {
+            let parent = current / 2;
+            let concatenation = concatenate_hashes(
+                representation[2 * parent].clone(),
+                representation[2 * parent + 1].clone(),
+            );
+            representation[parent] = hash(concatenation);
+            current = parent;
+        }
+    }
+}
+
+fn bench_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new_depth_20");
+    group.sample_size(10);
+    group.bench_function("vec_of_vecs", |b| {
+        b.iter(|| vec_of_vecs::new(DEPTH + 1, INITIAL_LEAF))
+    });
+    group.bench_function("flat_hash32", |b| {
+        b.iter(|| <MerkleTree>::new(DEPTH, INITIAL_LEAF.to_owned()))
+    });
+    group.finish();
+}
+
+fn bench_set(c: &mut Criterion) {
+    let value = "0x1111111111111111111111111111111111111111111111111111111111111111";
+    let mut group = c.benchmark_group("set_depth_20");
+    group.bench_function("vec_of_vecs", |b| {
+        let mut representation = vec_of_vecs::new(DEPTH + 1, INITIAL_LEAF);
+        let index = representation.len() / 2;
+        b.iter(|| {
+            vec_of_vecs::set(
+                &mut representation,
+                index,
+                hex::decode(&value[2..]).unwrap(),
+            )
+        })
+    });
+    group.bench_function("flat_hash32", |b| {
+        let mut mt = <MerkleTree>::new(DEPTH, INITIAL_LEAF.to_owned());
+        let index = mt.leaf_range().start;
+        b.iter(|| mt.set(index, value.to_owned()))
+    });
+    group.finish();
+}
+
+fn bench_set_many(c: &mut Criterion) {
+    // A batch of neighbouring leaves whose paths to the root overlap for most of their length
+    let updates: Vec<(usize, Hash32)> = (0..1024).map(|i| (i, Hash32([0x11; 32]))).collect();
+    let mut group = c.benchmark_group("set_1024_leaves_depth_20");
+    group.bench_function("set_one_by_one", |b| {
+        let mut mt = <MerkleTree>::new(DEPTH, INITIAL_LEAF.to_owned());
+        let start = mt.leaf_range().start;
+        b.iter(|| {
+            for (leaf_index, hash) in &updates {
+                mt.set(start + leaf_index, hash.to_hex())
+            }
+        })
+    });
+    group.bench_function("set_many", |b| {
+        let mut mt = <MerkleTree>::new(DEPTH, INITIAL_LEAF.to_owned());
+        b.iter(|| mt.set_many(&updates).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_from_leaves(c: &mut Criterion) {
+    // Distinct leaves, so no level can be filled with a single hash. Run with `--features rayon` to compare
+    let leaves: Vec<Hash32> = (0..1u32 << DEPTH)
+        .map(|i| Hash32::from(<[u8; 32]>::from(Sha3_256::digest(i.to_be_bytes()))))
+        .collect();
+    let mut group = c.benchmark_group("from_leaves_depth_20");
+    group.sample_size(10);
+    group.bench_function("distinct_leaves", |b| {
+        b.iter(|| <MerkleTree>::from_leaves(leaves.clone()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_new,
+    bench_set,
+    bench_set_many,
+    bench_from_leaves
+);
+criterion_main!(benches);
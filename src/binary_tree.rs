@@ -1,3 +1,5 @@
+use std::ops::{Index, IndexMut};
+
 // Behavior methods that are the same accross all types of binary trees extracted into this trait for reusability in the Merkle tree part
 pub trait BinaryTreeBehavior {
     // Exercise 1:
@@ -74,27 +76,235 @@ pub trait BinaryTreeBehavior {
 // What we are dealing with is a complete binary tree, a complete binary tree
 // is where every level is completely filled,
 // except maybe for the last level, which is filled from left to right.
-pub struct BinaryTree {
-    ds: Vec<Option<u32>>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryTree<T> {
+    ds: Vec<Option<T>>,
 }
-impl BinaryTree {
+
+impl<T> BinaryTree<T> {
     // Create a binary tree represented in array form with a single root node
-    pub fn new(root_value: u32) -> Self {
+    pub fn new(root_value: T) -> Self {
         // We are always going to occupy the first index with a None value in order to make index calculations a breeze
         BinaryTree {
             ds: vec![None, Some(root_value)],
         }
     }
 
-    pub fn get_array_representation(&self) -> Vec<Option<u32>> {
+    pub fn get_array_representation(&self) -> Vec<Option<T>>
+    where
+        T: Clone,
+    {
         self.ds.clone()
     }
 
-    pub fn add(&mut self, value: u32) {
+    pub fn add(&mut self, value: T) {
         self.ds.push(Some(value))
     }
-    pub fn get(&self, index: u32) -> Option<u32> {
-        self.ds[index as usize]
+
+    /// Returns the value of the node at index, None if there is no such node
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the binary tree
+    ///
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.ds.get(index)?.as_ref()
+    }
+
+    /// Returns a mutable reference to the value of the node at index, None if there is no such node
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the binary tree
+    ///
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.ds.get_mut(index)?.as_mut()
+    }
+
+    /// Replaces the value of the node at index and returns the previous value, or None if there is no such node in
+    /// which case the tree is left unchanged
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the binary tree
+    /// * `value` - The new value of the node
+    ///
+    pub fn set(&mut self, index: usize, value: T) -> Option<T> {
+        let slot = self.get_mut(index)?;
+        Some(std::mem::replace(slot, value))
+    }
+
+    /// Swaps the values of two nodes
+    ///
+    /// Panics if either index is not a node of the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The index of the first node
+    /// * `j` - The index of the second node
+    ///
+    pub fn swap(&mut self, i: usize, j: usize) {
+        if i == 0 || j == 0 {
+            panic!("This binary tree uses one based indexing")
+        }
+        self.ds.swap(i, j)
+    }
+
+    /// Removes the last node, the rightmost one of the deepest level, and returns its value, None if the tree is empty
+    pub fn pop_last(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.ds.pop().flatten()
+    }
+
+    /// Removes the node at index and returns its value, None if there is no such node. The last node takes its place
+    /// so the tree stays complete, which changes the order of the values unless the last node itself is removed
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the binary tree
+    ///
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index == 0 || index > self.len() {
+            return None;
+        }
+        let last = self.len();
+        self.ds.swap(index, last);
+        self.pop_last()
+    }
+
+    /// Returns a copy of the subtree rooted at index as a tree of its own, None if there is no such node
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the index of the root of the subtree
+    ///
+    pub fn subtree(&self, index: usize) -> Option<BinaryTree<T>>
+    where
+        T: Clone,
+    {
+        self.get(index)?;
+        // The subtree holds 2^k nodes of each level k below its root, starting at index * 2^k. Since the last level of
+        // the tree is filled from the left, the nodes of the subtree on it are filled from the left as well
+        let mut subtree = BinaryTree::default();
+        let mut start = index;
+        let mut width = 1;
+        while start <= self.len() {
+            let end = (start + width).min(self.len() + 1);
+            subtree.ds.extend_from_slice(&self.ds[start..end]);
+            start = Self::get_left_child(start);
+            width *= 2;
+        }
+        Some(subtree)
+    }
+
+    /// Returns the number of nodes in the tree, the slot reserved at index 0 is not counted
+    pub fn len(&self) -> usize {
+        self.ds.len() - 1
+    }
+
+    /// Returns true if the tree has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the values of the nodes in the order of their indices, which is level by level from left to right
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.ds.iter().skip(1).flatten()
+    }
+
+    /// Returns mutable references to the values of the nodes in the order of their indices
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.ds.iter_mut().skip(1).flatten()
+    }
+
+    /// Returns the values of the nodes in the given order, borrowed from the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the nodes are visited
+    ///
+    pub fn traverse(&self, order: Order) -> Traversal<'_, T> {
+        Traversal {
+            ds: &self.ds,
+            walk: Walk::new(order, self.len()),
+        }
+    }
+
+    /// Returns the values of the nodes in the given order, moved out of the tree
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the nodes are visited
+    ///
+    pub fn into_traverse(self, order: Order) -> IntoTraversal<T> {
+        let walk = Walk::new(order, self.len());
+        IntoTraversal { ds: self.ds, walk }
+    }
+
+    /// Returns the values of the nodes with every node before its left and then its right subtree
+    pub fn pre_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::PreOrder)
+    }
+
+    /// Returns the values of the nodes with every node between its left and its right subtree
+    pub fn in_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::InOrder)
+    }
+
+    /// Returns the values of the nodes with every node after its left and its right subtree
+    pub fn post_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::PostOrder)
+    }
+
+    /// Returns the values of the nodes level by level from left to right, which is the order of their indices
+    pub fn level_order(&self) -> Traversal<'_, T> {
+        self.traverse(Order::LevelOrder)
+    }
+
+    /// Returns the values of the nodes without children from left to right
+    pub fn leaves(&self) -> Traversal<'_, T> {
+        self.traverse(Order::Leaves)
+    }
+
+    /// Moves the values of the nodes out of the tree in pre-order
+    pub fn into_pre_order(self) -> IntoTraversal<T> {
+        self.into_traverse(Order::PreOrder)
+    }
+
+    /// Moves the values of the nodes out of the tree in in-order
+    pub fn into_in_order(self) -> IntoTraversal<T> {
+        self.into_traverse(Order::InOrder)
+    }
+
+    /// Moves the values of the nodes out of the tree in post-order
+    pub fn into_post_order(self) -> IntoTraversal<T> {
+        self.into_traverse(Order::PostOrder)
+    }
+
+    /// Moves the values of the nodes out of the tree level by level
+    pub fn into_level_order(self) -> IntoTraversal<T> {
+        self.into_traverse(Order::LevelOrder)
+    }
+
+    /// Moves the values of the nodes without children out of the tree, dropping every other node
+    pub fn into_leaves(self) -> IntoTraversal<T> {
+        self.into_traverse(Order::Leaves)
+    }
+
+    /// Calls the visitor with the depth, the offset at that depth and the value of every node in the given order
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order in which the nodes are visited
+    /// * `visitor` - A function called with the depth, offset and value of each node
+    ///
+    pub fn visit<F: FnMut(u32, u32, &T)>(&self, order: Order, mut visitor: F) {
+        for index in Walk::new(order, self.len()) {
+            let (depth, offset) = Self::get_depth_and_offset(index);
+            visitor(depth, offset, &self[index]);
+        }
     }
 
     // The height of a array represented, complete, binary tree is the node count
@@ -105,7 +315,200 @@ impl BinaryTree {
     }
 }
 
-impl BinaryTreeBehavior for BinaryTree {}
+/// The orders in which the nodes of a binary tree can be traversed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// A node, then its left subtree, then its right subtree
+    PreOrder,
+    /// The left subtree of a node, then the node, then its right subtree
+    InOrder,
+    /// The left subtree of a node, then its right subtree, then the node
+    PostOrder,
+    /// Level by level from the root down, each level from left to right
+    LevelOrder,
+    /// Only the nodes without children, from left to right
+    Leaves,
+}
+
+// Walks the indices of a complete binary tree of len nodes in an order. The nodes are found with the index calculus
+// instead of being collected up front, so only the path from the root to the current node is kept on the stack
+#[derive(Debug, Clone)]
+struct Walk {
+    order: Order,
+    len: usize,
+    stack: Vec<usize>,
+    // The node to descend from next in in-order and post-order, the next index in level order and for leaves
+    next: usize,
+    // The node returned last in post-order, so a parent knows its right subtree has been walked
+    last: usize,
+}
+
+impl BinaryTreeBehavior for Walk {}
+
+impl Walk {
+    fn new(order: Order, len: usize) -> Self {
+        let (stack, next) = match order {
+            Order::PreOrder if len > 0 => (vec![1], 0),
+            Order::PreOrder => (Vec::new(), 0),
+            Order::InOrder | Order::PostOrder | Order::LevelOrder => (Vec::new(), 1),
+            // Nodes without a left child are the leaves, they make up the second half of the array
+            Order::Leaves => (Vec::new(), len / 2 + 1),
+        };
+        Walk {
+            order,
+            len,
+            stack,
+            next,
+            last: 0,
+        }
+    }
+
+    // Pushes the node to descend from and all of its left descendants
+    fn descend(&mut self) {
+        while self.next != 0 && self.next <= self.len {
+            self.stack.push(self.next);
+            self.next = Self::get_left_child(self.next);
+        }
+        self.next = 0;
+    }
+}
+
+impl Iterator for Walk {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self.order {
+            Order::PreOrder => {
+                let index = self.stack.pop()?;
+                for child in [Self::get_right_child(index), Self::get_left_child(index)] {
+                    if child <= self.len {
+                        self.stack.push(child);
+                    }
+                }
+                Some(index)
+            }
+            Order::InOrder => {
+                self.descend();
+                let index = self.stack.pop()?;
+                self.next = Self::get_right_child(index);
+                Some(index)
+            }
+            Order::PostOrder => loop {
+                self.descend();
+                let index = *self.stack.last()?;
+                let right = Self::get_right_child(index);
+                if right <= self.len && self.last != right {
+                    self.next = right;
+                    continue;
+                }
+                self.stack.pop();
+                self.last = index;
+                return Some(index);
+            },
+            Order::LevelOrder | Order::Leaves => {
+                let index = self.next;
+                if index > self.len {
+                    return None;
+                }
+                self.next += 1;
+                Some(index)
+            }
+        }
+    }
+}
+
+/// An iterator over the values of a binary tree in one of the traversal orders
+#[derive(Debug, Clone)]
+pub struct Traversal<'a, T> {
+    ds: &'a [Option<T>],
+    walk: Walk,
+}
+
+impl<'a, T> Iterator for Traversal<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let index = self.walk.next()?;
+        self.ds[index].as_ref()
+    }
+}
+
+/// An iterator moving the values out of a binary tree in one of the traversal orders
+#[derive(Debug, Clone)]
+pub struct IntoTraversal<T> {
+    ds: Vec<Option<T>>,
+    walk: Walk,
+}
+
+impl<T> Iterator for IntoTraversal<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.walk.next()?;
+        self.ds[index].take()
+    }
+}
+
+// Collecting values into a tree fills it level by level from left to right, the same order add appends them in
+impl<T> FromIterator<T> for BinaryTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut tree = BinaryTree::default();
+        tree.ds.extend(values.into_iter().map(Some));
+        tree
+    }
+}
+
+// Iterating over a tree without picking an order goes level by level
+impl<'a, T> IntoIterator for &'a BinaryTree<T> {
+    type Item = &'a T;
+    type IntoIter = Traversal<'a, T>;
+
+    fn into_iter(self) -> Traversal<'a, T> {
+        self.level_order()
+    }
+}
+
+impl<T> IntoIterator for BinaryTree<T> {
+    type Item = T;
+    type IntoIter = IntoTraversal<T>;
+
+    fn into_iter(self) -> IntoTraversal<T> {
+        self.into_level_order()
+    }
+}
+
+// An empty tree only holds the slot reserved at index 0
+impl<T> Default for BinaryTree<T> {
+    fn default() -> Self {
+        BinaryTree { ds: vec![None] }
+    }
+}
+
+impl<T> Index<usize> for BinaryTree<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        if index == 0 {
+            panic!("This binary tree uses one based indexing")
+        }
+        self.ds[index]
+            .as_ref()
+            .expect("Every slot after the first holds a node")
+    }
+}
+
+impl<T> IndexMut<usize> for BinaryTree<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        if index == 0 {
+            panic!("This binary tree uses one based indexing")
+        }
+        self.ds[index]
+            .as_mut()
+            .expect("Every slot after the first holds a node")
+    }
+}
+
+impl<T> BinaryTreeBehavior for BinaryTree<T> {}
 
 #[cfg(test)]
 mod tests {
@@ -113,7 +516,7 @@ mod tests {
 
     use super::*;
 
-    fn create_complete_binary_tree() -> BinaryTree {
+    fn create_complete_binary_tree() -> BinaryTree<u32> {
         let mut bt = BinaryTree::new(0);
         for value in 1..14 {
             bt.add(value)
@@ -152,21 +555,21 @@ mod tests {
 
     #[test]
     fn should_get_node_index_from_depth_offset_pair() {
-        let root_node_index = BinaryTree::get_node_index(0, 0);
+        let root_node_index = BinaryTree::<u32>::get_node_index(0, 0);
 
         assert_eq!(root_node_index, 1);
 
-        let last_node_index = BinaryTree::get_node_index(3, 7);
+        let last_node_index = BinaryTree::<u32>::get_node_index(3, 7);
         assert_eq!(last_node_index, 15);
     }
 
     #[test]
     fn should_get_depth_and_offset_from_index() {
-        let root_depth_and_offset = BinaryTree::get_depth_and_offset(1);
+        let root_depth_and_offset = BinaryTree::<u32>::get_depth_and_offset(1);
 
         assert_eq!(root_depth_and_offset, (0, 0));
 
-        let last_node_depth_and_offset = BinaryTree::get_depth_and_offset(15);
+        let last_node_depth_and_offset = BinaryTree::<u32>::get_depth_and_offset(15);
         assert_eq!(last_node_depth_and_offset, (3, 7));
     }
 
@@ -174,21 +577,193 @@ mod tests {
     fn should_return_parent_index() {
         let bt = create_complete_binary_tree();
 
-        let root_as_parent = binary_tree::BinaryTree::get_parent(2);
+        let root_as_parent = binary_tree::BinaryTree::<u32>::get_parent(2);
         assert_eq!(root_as_parent, 1);
 
         // In a complete binary tree the last node of three depths has a parent with value 6, stored ad index 7
-        let parent_of_last_node = binary_tree::BinaryTree::get_parent(15);
+        let parent_of_last_node = binary_tree::BinaryTree::<u32>::get_parent(15);
         assert_eq!(parent_of_last_node, 7);
-        assert_eq!(bt.get(7).unwrap(), 6);
+        assert_eq!(bt.get(7), Some(&6));
     }
 
     #[test]
     fn should_return_left_child_index() {
-        let left_child_of_root = binary_tree::BinaryTree::get_left_child(1);
+        let left_child_of_root = binary_tree::BinaryTree::<u32>::get_left_child(1);
         assert_eq!(left_child_of_root, 2);
 
-        let left_most_child = binary_tree::BinaryTree::get_left_child(4);
+        let left_most_child = binary_tree::BinaryTree::<u32>::get_left_child(4);
         assert_eq!(left_most_child, 8);
     }
+
+    #[test]
+    fn should_hold_values_of_any_type() {
+        let mut bt = BinaryTree::new(String::from("root"));
+        bt.add(String::from("left"));
+        bt.add(String::from("right"));
+
+        assert_eq!(bt.len(), 3);
+        assert!(!bt.is_empty());
+        assert_eq!(bt[2], "left");
+        assert_eq!(bt.get(3).map(String::as_str), Some("right"));
+        assert_eq!(bt.get(0), None);
+        assert_eq!(bt.get(4), None);
+
+        bt[1].push_str(" node");
+        if let Some(right) = bt.get_mut(3) {
+            right.make_ascii_uppercase();
+        }
+        assert_eq!(
+            bt.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["root node", "left", "RIGHT"]
+        );
+    }
+
+    #[test]
+    fn should_iterate_and_mutate_in_index_order() {
+        let mut bt = create_complete_binary_tree();
+        for value in bt.iter_mut() {
+            *value *= 2;
+        }
+        assert_eq!(
+            bt.iter().copied().collect::<Vec<_>>(),
+            (0..14).map(|v| v * 2).collect::<Vec<_>>()
+        );
+
+        let empty: BinaryTree<u32> = BinaryTree::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().next(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "one based indexing")]
+    fn should_not_index_the_reserved_slot() {
+        let bt = create_complete_binary_tree();
+        let _ = bt[0];
+    }
+
+    // Values are the index minus one, so the orders can be read off as indices
+    fn values<'a, I: Iterator<Item = &'a u32>>(iter: I) -> Vec<u32> {
+        iter.copied().collect()
+    }
+
+    #[test]
+    fn should_traverse_a_perfect_tree_in_every_order() {
+        let mut bt = BinaryTree::new(0);
+        for value in 1..7 {
+            bt.add(value)
+        }
+
+        assert_eq!(values(bt.pre_order()), [0, 1, 3, 4, 2, 5, 6]);
+        assert_eq!(values(bt.in_order()), [3, 1, 4, 0, 5, 2, 6]);
+        assert_eq!(values(bt.post_order()), [3, 4, 1, 5, 6, 2, 0]);
+        assert_eq!(values(bt.level_order()), [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(values(bt.leaves()), [3, 4, 5, 6]);
+        assert_eq!(values(bt.iter()), values((&bt).into_iter()));
+    }
+
+    #[test]
+    fn should_traverse_a_tree_whose_last_level_is_not_full() {
+        let mut bt = BinaryTree::new(0);
+        for value in 1..6 {
+            bt.add(value)
+        }
+
+        assert_eq!(values(bt.pre_order()), [0, 1, 3, 4, 2, 5]);
+        assert_eq!(values(bt.in_order()), [3, 1, 4, 0, 5, 2]);
+        assert_eq!(values(bt.post_order()), [3, 4, 1, 5, 2, 0]);
+        assert_eq!(values(bt.leaves()), [3, 4, 5]);
+
+        assert_eq!(
+            bt.clone().into_post_order().collect::<Vec<_>>(),
+            [3, 4, 1, 5, 2, 0]
+        );
+        assert_eq!(bt.clone().into_leaves().collect::<Vec<_>>(), [3, 4, 5]);
+        assert_eq!(bt.into_iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+
+        let empty: BinaryTree<u32> = BinaryTree::default();
+        for order in [
+            Order::PreOrder,
+            Order::InOrder,
+            Order::PostOrder,
+            Order::LevelOrder,
+            Order::Leaves,
+        ] {
+            assert_eq!(empty.traverse(order).next(), None);
+        }
+    }
+
+    #[test]
+    fn should_visit_nodes_with_their_depth_and_offset() {
+        let bt = create_complete_binary_tree();
+        let mut visited = Vec::new();
+        bt.visit(Order::InOrder, |depth, offset, value| {
+            visited.push((depth, offset, *value))
+        });
+
+        assert_eq!(visited.len(), bt.len());
+        assert_eq!(visited[0], (3, 0, 7));
+        assert_eq!(visited[7], (0, 0, 0));
+        for (depth, offset, value) in visited {
+            assert_eq!(BinaryTree::<u32>::get_node_index(depth, offset), value + 1);
+        }
+    }
+
+    #[test]
+    fn should_set_and_swap_values() {
+        let mut bt = create_complete_binary_tree();
+        assert_eq!(bt.set(3, 20), Some(2));
+        assert_eq!(bt.set(15, 20), None);
+        assert_eq!(bt.len(), 14);
+
+        bt.swap(1, 3);
+        assert_eq!(bt[1], 20);
+        assert_eq!(bt[3], 0);
+    }
+
+    #[test]
+    fn should_remove_nodes_while_keeping_the_tree_complete() {
+        let mut bt = create_complete_binary_tree();
+        assert_eq!(bt.pop_last(), Some(13));
+        assert_eq!(bt.len(), 13);
+
+        // The last node, holding 12, moves into the slot of the removed one
+        assert_eq!(bt.remove_at(2), Some(1));
+        assert_eq!(bt.len(), 12);
+        assert_eq!(bt[2], 12);
+        assert_eq!(bt.remove_at(12), Some(11));
+        assert_eq!(bt.remove_at(12), None);
+        assert_eq!(bt.remove_at(0), None);
+
+        let mut single = BinaryTree::new(0);
+        assert_eq!(single.remove_at(1), Some(0));
+        assert!(single.is_empty());
+        assert_eq!(single.pop_last(), None);
+        assert_eq!(single.get_array_representation(), vec![None]);
+    }
+
+    #[test]
+    fn should_extract_a_subtree() {
+        let bt = create_complete_binary_tree();
+
+        // Index 2 has the children 4 and 5, which have the children 8 to 11
+        let left = bt.subtree(2).unwrap();
+        assert_eq!(values(left.level_order()), [1, 3, 4, 7, 8, 9, 10]);
+        // Index 3 has the children 6 and 7, but only 12 to 14 of their children exist
+        let right = bt.subtree(3).unwrap();
+        assert_eq!(values(right.level_order()), [2, 5, 6, 11, 12, 13]);
+        assert_eq!(values(right.in_order()), [11, 5, 12, 2, 13, 6]);
+
+        assert_eq!(
+            bt.subtree(14).unwrap().get_array_representation(),
+            vec![None, Some(13)]
+        );
+        assert_eq!(bt.subtree(15), None);
+        assert_eq!(bt.subtree(1), Some(bt));
+    }
+
+    #[test]
+    fn should_collect_a_binary_tree_from_an_iterator() {
+        let bt = create_complete_binary_tree();
+        assert_eq!(bt.iter().copied().collect::<BinaryTree<_>>(), bt);
+    }
 }
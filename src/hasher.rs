@@ -0,0 +1,489 @@
+use std::fmt;
+
+use hex::FromHex;
+use sha3::Digest;
+
+use crate::merkle_tree::{Error, HASH_LENGTH};
+
+/// A 32 byte digest, the fixed size node type stored in the merkle trees
+///
+/// It has the layout of its bytes, so a buffer of bytes can be viewed as a slice of hashes
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Hash32(pub [u8; HASH_LENGTH]);
+
+impl Hash32 {
+    /// Decodes a hash from its hexadecimal string representation in either case, the 0x or 0X prefix is optional
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - A hexadecimal string of exactly 32 bytes
+    ///
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        let without_prefix = match s.get(..2) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("0x") => &s[2..],
+            _ => s,
+        };
+        let bytes = Vec::<u8>::from_hex(without_prefix)?;
+        Self::from_slice(&bytes)
+    }
+
+    /// Copies a hash out of a byte slice, failing if the slice is not exactly 32 bytes long
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes of the hash
+    ///
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        let array: [u8; HASH_LENGTH] = bytes.try_into().map_err(|_| Error::InvalidHashLength {
+            expected: HASH_LENGTH,
+            actual: bytes.len(),
+        })?;
+        Ok(Hash32(array))
+    }
+
+    /// Returns the 0x prefixed lowercase hexadecimal representation of the hash
+    pub fn to_hex(&self) -> String {
+        String::from("0x") + &hex::encode(self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; HASH_LENGTH] {
+        &self.0
+    }
+}
+
+impl From<[u8; HASH_LENGTH]> for Hash32 {
+    fn from(bytes: [u8; HASH_LENGTH]) -> Self {
+        Hash32(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Hash32 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Hash32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for Hash32 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash32({})", self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hash32 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Hashes are hex strings in formats meant for people, such as JSON, and plain bytes in binary formats such as CBOR
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Hash32 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Hash32Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Hash32Visitor {
+            type Value = Hash32;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a 32 byte hash as a hexadecimal string or as bytes")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Hash32, E> {
+                Hash32::from_hex(v).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Hash32, E> {
+                Hash32::from_slice(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Hash32, A::Error> {
+                let mut bytes = Vec::with_capacity(HASH_LENGTH);
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Hash32::from_slice(&bytes).map_err(serde::de::Error::custom)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Hash32Visitor)
+        } else {
+            deserializer.deserialize_bytes(Hash32Visitor)
+        }
+    }
+}
+
+// The hash function used to combine nodes of a merkle tree is pluggable, each implementation of this trait
+// is a zero sized marker type so the choice is made through a type parameter and costs nothing at runtime
+pub trait Hasher: Clone + Copy + fmt::Debug + Default + PartialEq + Eq {
+    /// Identifies the hash function in serialized trees, so a tree is never loaded with another hash function than
+    /// it was built with. The hashers of this crate use the ids below 0x80
+    const ALGORITHM_ID: u8;
+
+    /// Returns the 32 byte digest of the given bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to hash
+    ///
+    fn hash(data: &[u8]) -> Hash32;
+
+    /// Returns the digest of the concatenation of two hashes, the way parent nodes are computed from their children
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    ///
+    fn hash_pair(left: &Hash32, right: &Hash32) -> Hash32 {
+        // Concatenate on the stack to avoid a heap allocation per node
+        let mut concatenation = [0u8; 2 * HASH_LENGTH];
+        concatenation[..HASH_LENGTH].copy_from_slice(&left.0);
+        concatenation[HASH_LENGTH..].copy_from_slice(&right.0);
+        Self::hash(&concatenation)
+    }
+
+    /// Returns the digest of the leaf data prefixed with the 0x00 byte, the leaf hash of RFC 6962
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The leaf data to hash
+    ///
+    fn hash_prefixed_leaf(data: &[u8]) -> Hash32 {
+        let mut prefixed = Vec::with_capacity(data.len() + 1);
+        prefixed.push(LEAF_PREFIX);
+        prefixed.extend_from_slice(data);
+        Self::hash(&prefixed)
+    }
+
+    /// Returns the digest of the concatenation of two hashes prefixed with the 0x01 byte, the node hash of RFC 6962
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The hash of the left child
+    /// * `right` - The hash of the right child
+    ///
+    fn hash_prefixed_pair(left: &Hash32, right: &Hash32) -> Hash32 {
+        let mut concatenation = [0u8; 2 * HASH_LENGTH + 1];
+        concatenation[0] = NODE_PREFIX;
+        concatenation[1..HASH_LENGTH + 1].copy_from_slice(&left.0);
+        concatenation[HASH_LENGTH + 1..].copy_from_slice(&right.0);
+        Self::hash(&concatenation)
+    }
+}
+
+/// The byte leaves are prefixed with before hashing in the RFC 6962 hashing mode
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// The byte intermediate nodes are prefixed with before hashing in the RFC 6962 hashing mode
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// Selects how leaves and intermediate nodes of a merkle tree are hashed
+///
+/// Without domain separation a 64 byte leaf and an intermediate node hash identically, which lets an intermediate
+/// node be passed off as a leaf. Prefixing leaves and intermediate nodes with different bytes rules that out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashingMode {
+    /// Leaves are stored as they are given and intermediate nodes are the hash of their children's concatenation
+    #[default]
+    Plain,
+    /// Leaves are stored as the hash of 0x00 followed by the leaf and intermediate nodes are the hash of 0x01 followed by their children, as in Certificate Transparency RFC 6962
+    ///
+    /// The leaf is the 32 byte hash given to the tree, so leaves are H(0x00 ‖ leaf hash) and not H(0x00 ‖ raw entry).
+    /// The root matches the one of RFC 6962 over the leaf hashes as entries, not over the raw data they were hashed from
+    Rfc6962,
+    /// Leaves are stored as they are given and intermediate nodes are the hash of their children sorted by value, so the
+    /// order of the children does not matter. This is how the OpenZeppelin MerkleProof contracts combine nodes
+    SortedPair,
+}
+
+impl HashingMode {
+    // The byte identifying the mode in binary encodings
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            HashingMode::Plain => 0,
+            HashingMode::Rfc6962 => 1,
+            HashingMode::SortedPair => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(HashingMode::Plain),
+            1 => Some(HashingMode::Rfc6962),
+            2 => Some(HashingMode::SortedPair),
+            _ => None,
+        }
+    }
+
+    /// Returns the value stored in the tree for a given leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The leaf as given by the caller
+    ///
+    pub fn leaf_node<H: Hasher>(self, leaf: &Hash32) -> Hash32 {
+        match self {
+            HashingMode::Plain | HashingMode::SortedPair => *leaf,
+            HashingMode::Rfc6962 => H::hash_prefixed_leaf(leaf.as_ref()),
+        }
+    }
+
+    /// Returns the value of an intermediate node given the values of its children
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The value of the left child
+    /// * `right` - The value of the right child
+    ///
+    pub fn parent_node<H: Hasher>(self, left: &Hash32, right: &Hash32) -> Hash32 {
+        match self {
+            HashingMode::Plain => H::hash_pair(left, right),
+            HashingMode::Rfc6962 => H::hash_prefixed_pair(left, right),
+            HashingMode::SortedPair if right < left => H::hash_pair(right, left),
+            HashingMode::SortedPair => H::hash_pair(left, right),
+        }
+    }
+}
+
+/// Selects how raw leaf data is turned into the leaf hash given to a merkle tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LeafHashing {
+    /// The leaf is the hash of the data
+    #[default]
+    Single,
+    /// The leaf is the hash of the hash of the data, as in the OpenZeppelin StandardMerkleTree. A leaf then never
+    /// has the preimage of an intermediate node, whose preimage is the 64 byte concatenation of its children
+    Double,
+}
+
+impl LeafHashing {
+    // The byte identifying the leaf hashing in binary encodings
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            LeafHashing::Single => 0,
+            LeafHashing::Double => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(LeafHashing::Single),
+            1 => Some(LeafHashing::Double),
+            _ => None,
+        }
+    }
+
+    /// Returns the leaf hash of the given data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes of the leaf
+    ///
+    pub fn hash_leaf<H: Hasher>(self, data: &[u8]) -> Hash32 {
+        match self {
+            LeafHashing::Single => H::hash(data),
+            LeafHashing::Double => H::hash(&H::hash(data).0),
+        }
+    }
+}
+
+/// SHA3-256 as standardized in FIPS 202, the default hash function of the merkle tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha3_256;
+
+impl Hasher for Sha3_256 {
+    const ALGORITHM_ID: u8 = 1;
+
+    fn hash(data: &[u8]) -> Hash32 {
+        Hash32(sha3::Sha3_256::digest(data).into())
+    }
+}
+
+/// Keccak-256 as used by Ethereum, which differs from SHA3-256 only in its padding
+#[cfg(feature = "keccak")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keccak256;
+
+#[cfg(feature = "keccak")]
+impl Hasher for Keccak256 {
+    const ALGORITHM_ID: u8 = 2;
+
+    fn hash(data: &[u8]) -> Hash32 {
+        Hash32(sha3::Keccak256::digest(data).into())
+    }
+}
+
+/// SHA-256 as standardized in FIPS 180-4
+#[cfg(feature = "sha256")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256;
+
+#[cfg(feature = "sha256")]
+impl Hasher for Sha256 {
+    const ALGORITHM_ID: u8 = 3;
+
+    fn hash(data: &[u8]) -> Hash32 {
+        Hash32(sha2::Sha256::digest(data).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_a_hash_from_hex_with_or_without_prefix() {
+        let with_prefix =
+            Hash32::from_hex("0xABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABAB")
+                .unwrap();
+        let without_prefix =
+            Hash32::from_hex("abababababababababababababababababababababababababababababababab")
+                .unwrap();
+        assert_eq!(with_prefix, without_prefix);
+        assert_eq!(with_prefix, Hash32([0xab; 32]));
+        assert_eq!(
+            Hash32::from_hex("0XABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABABAB"),
+            Ok(with_prefix)
+        );
+        assert_eq!(
+            with_prefix.to_hex(),
+            "0xabababababababababababababababababababababababababababababababab"
+        );
+    }
+
+    #[test]
+    fn should_refuse_to_decode_a_hash_of_the_wrong_length() {
+        assert_eq!(
+            Hash32::from_hex("0xabab"),
+            Err(Error::InvalidHashLength {
+                expected: 32,
+                actual: 2
+            })
+        );
+    }
+
+    #[test]
+    fn should_hash_a_pair_as_the_concatenation_of_both_hashes() {
+        let left = Hash32([1; 32]);
+        let right = Hash32([2; 32]);
+        assert_eq!(
+            Sha3_256::hash_pair(&left, &right),
+            Sha3_256::hash(&[left.0, right.0].concat())
+        );
+    }
+
+    #[test]
+    fn should_leave_leaves_untouched_in_plain_mode() {
+        let leaf = Hash32([7; 32]);
+        assert_eq!(HashingMode::Plain.leaf_node::<Sha3_256>(&leaf), leaf);
+        assert_ne!(HashingMode::Rfc6962.leaf_node::<Sha3_256>(&leaf), leaf);
+    }
+
+    #[test]
+    fn should_separate_leaf_and_node_hashes_in_rfc_6962_mode() {
+        let left = Hash32([1; 32]);
+        let right = Hash32([2; 32]);
+        let concatenation = [left.0, right.0].concat();
+        assert_ne!(
+            HashingMode::Rfc6962.parent_node::<Sha3_256>(&left, &right),
+            Sha3_256::hash_prefixed_leaf(&concatenation),
+            "An intermediate node should never hash like a leaf with the same bytes"
+        );
+        assert_eq!(
+            HashingMode::Plain.parent_node::<Sha3_256>(&left, &right),
+            Sha3_256::hash(&concatenation)
+        );
+    }
+
+    #[test]
+    fn should_combine_children_regardless_of_their_order_in_sorted_pair_mode() {
+        let small = Hash32([1; 32]);
+        let large = Hash32([2; 32]);
+        assert_eq!(
+            HashingMode::SortedPair.parent_node::<Sha3_256>(&large, &small),
+            HashingMode::SortedPair.parent_node::<Sha3_256>(&small, &large)
+        );
+        assert_eq!(
+            HashingMode::SortedPair.parent_node::<Sha3_256>(&large, &small),
+            Sha3_256::hash_pair(&small, &large)
+        );
+    }
+
+    // Test vectors of the Certificate Transparency reference implementation of RFC 6962
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn should_match_the_rfc_6962_test_vectors() {
+        let leaves = [
+            "",
+            "00",
+            "10",
+            "2021",
+            "3031",
+            "40414243",
+            "5051525354555657",
+            "606162636465666768696a6b6c6d6e6f",
+        ];
+        let mut level: Vec<Hash32> = leaves
+            .iter()
+            .map(|leaf| Sha256::hash_prefixed_leaf(&hex::decode(leaf).unwrap()))
+            .collect();
+        assert_eq!(
+            level[0].to_hex(),
+            "0x6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| HashingMode::Rfc6962.parent_node::<Sha256>(&pair[0], &pair[1]))
+                .collect();
+        }
+        assert_eq!(
+            level[0].to_hex(),
+            "0x5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328"
+        );
+    }
+
+    #[test]
+    fn should_hash_with_sha3_256() {
+        assert_eq!(
+            hex::encode(Sha3_256::hash(b"").0),
+            "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+        );
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn should_hash_with_keccak_256() {
+        assert_eq!(
+            hex::encode(Keccak256::hash(b"").0),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn should_hash_with_sha_256() {
+        assert_eq!(
+            hex::encode(Sha256::hash(b"").0),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
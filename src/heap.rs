@@ -0,0 +1,376 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::binary_tree::{BinaryTree, BinaryTreeBehavior};
+
+// A binary heap is a complete binary tree in which every node is ordered before its children, so the node ordered
+// first of all sits at the root. Its array form is the one of BinaryTree, which keeps the tree complete while nodes
+// are added at and removed from the end. A node out of place is moved up towards the root or down towards the leaves
+// by swapping it with its parent or a child until the order holds again.
+/// A binary heap with a min or max ordering given by a comparator
+///
+/// Nodes are addressed by their index in the array form of the tree. The root is at index 1, and the index of a value
+/// changes whenever the heap is changed.
+#[derive(Clone)]
+pub struct Heap<T, F = fn(&T, &T) -> Ordering> {
+    tree: BinaryTree<T>,
+    kind: Kind,
+    compare: F,
+}
+
+/// Whether the smallest or the largest value according to the comparator is at the top of a heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Min,
+    Max,
+}
+
+/// Errors returned by the heap operations that address a node by its index
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// There is no node at the index
+    NotANode(usize),
+    /// decrease_key was given a value larger than the one at the index
+    KeyIncreased(usize),
+    /// increase_key was given a value smaller than the one at the index
+    KeyDecreased(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotANode(index) => write!(f, "index {} is not a node of the heap", index),
+            Error::KeyIncreased(index) => {
+                write!(f, "new value is larger than the value at index {}", index)
+            }
+            Error::KeyDecreased(index) => {
+                write!(f, "new value is smaller than the value at index {}", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Kind {
+    // Returns true if a value that compares to another with the given ordering belongs above it
+    pub(crate) fn is_above(self, ordering: Ordering) -> bool {
+        match self {
+            Kind::Min => ordering == Ordering::Less,
+            Kind::Max => ordering == Ordering::Greater,
+        }
+    }
+}
+
+// Moving nodes into place is shared by every heap in the array form of BinaryTree. An implementor says how two nodes
+// are ordered and how they are swapped, which lets it keep anything that depends on the positions of its nodes up to
+// date, and gets the moves up and down the tree on top.
+pub(crate) trait Sift: BinaryTreeBehavior {
+    // Returns the number of nodes
+    fn len(&self) -> usize;
+
+    // Returns true if the node at index a belongs above the node at index b
+    fn is_above(&self, a: usize, b: usize) -> bool;
+
+    // Swaps the values of two nodes
+    fn swap(&mut self, i: usize, j: usize);
+
+    // Swaps a node with its parent for as long as it belongs above it, returning the index it ends up at
+    fn sift_up(&mut self, mut index: usize) -> usize {
+        while index > 1 {
+            let parent = Self::get_parent(index);
+            if !self.is_above(index, parent) {
+                break;
+            }
+            self.swap(index, parent);
+            index = parent;
+        }
+        index
+    }
+
+    // Swaps a node with the child that belongs highest for as long as that child belongs above it, returning the
+    // index it ends up at
+    fn sift_down(&mut self, mut index: usize) -> usize {
+        loop {
+            let left = Self::get_left_child(index);
+            if left > self.len() {
+                return index;
+            }
+            let right = Self::get_right_child(index);
+            let child = if right <= self.len() && self.is_above(right, left) {
+                right
+            } else {
+                left
+            };
+            if !self.is_above(child, index) {
+                return index;
+            }
+            self.swap(index, child);
+            index = child;
+        }
+    }
+}
+
+impl<T: Ord> Heap<T> {
+    /// Creates an empty heap ordered by the natural order of its values
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value is at the top
+    ///
+    pub fn new(kind: Kind) -> Self {
+        Self::with_comparator(kind, T::cmp)
+    }
+
+    /// Creates a heap ordered by the natural order of its values from a list of values in O(n)
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value is at the top
+    /// * `values` - The values of the heap in any order
+    ///
+    pub fn from_vec(kind: Kind, values: Vec<T>) -> Self {
+        Self::heapify(kind, values, T::cmp)
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Heap<T, F> {
+    /// Creates an empty heap ordered by a comparator
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value according to the comparator is at the top
+    /// * `compare` - Orders two values the same way as the comparators of slice::sort_by
+    ///
+    pub fn with_comparator(kind: Kind, compare: F) -> Self {
+        Heap {
+            tree: BinaryTree::default(),
+            kind,
+            compare,
+        }
+    }
+
+    /// Creates a heap ordered by a comparator from a list of values in O(n), by moving every node that has children
+    /// down into place, starting at the last one
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value according to the comparator is at the top
+    /// * `values` - The values of the heap in any order
+    /// * `compare` - Orders two values the same way as the comparators of slice::sort_by
+    ///
+    pub fn heapify(kind: Kind, values: Vec<T>, compare: F) -> Self {
+        let mut heap = Heap {
+            tree: values.into_iter().collect(),
+            kind,
+            compare,
+        };
+        for index in (1..=Self::get_parent(heap.len())).rev() {
+            heap.sift_down(index);
+        }
+        heap
+    }
+
+    /// Returns whether the smallest or the largest value is at the top
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Returns the number of values in the heap
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the heap holds no values
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the value at the top of the heap, None if the heap is empty
+    pub fn peek(&self) -> Option<&T> {
+        self.tree.get(1)
+    }
+
+    /// Returns the value of the node at index, None if there is no such node
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the heap
+    ///
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.get(index)
+    }
+
+    /// Adds a value in O(log n) and returns the index it ends up at
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to add
+    ///
+    pub fn push(&mut self, value: T) -> usize {
+        self.tree.add(value);
+        self.sift_up(self.len())
+    }
+
+    /// Removes the value at the top of the heap in O(log n) and returns it, None if the heap is empty
+    pub fn pop(&mut self) -> Option<T> {
+        let top = self.tree.remove_at(1)?;
+        if !self.is_empty() {
+            self.sift_down(1);
+        }
+        Some(top)
+    }
+
+    /// Replaces the value at index with a smaller one and moves it into place, returning its new index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the heap
+    /// * `value` - The new value, which must not be larger than the current one according to the comparator
+    ///
+    pub fn decrease_key(&mut self, index: usize, value: T) -> Result<usize, Error> {
+        let current = self.tree.get(index).ok_or(Error::NotANode(index))?;
+        if (self.compare)(&value, current) == Ordering::Greater {
+            return Err(Error::KeyIncreased(index));
+        }
+        Ok(self.replace(index, value))
+    }
+
+    /// Replaces the value at index with a larger one and moves it into place, returning its new index
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the a nodes index in the array representation of the heap
+    /// * `value` - The new value, which must not be smaller than the current one according to the comparator
+    ///
+    pub fn increase_key(&mut self, index: usize, value: T) -> Result<usize, Error> {
+        let current = self.tree.get(index).ok_or(Error::NotANode(index))?;
+        if (self.compare)(&value, current) == Ordering::Less {
+            return Err(Error::KeyDecreased(index));
+        }
+        Ok(self.replace(index, value))
+    }
+
+    /// Moves the values out of the heap in the order they would be popped in
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut sorted = Vec::with_capacity(self.len());
+        while let Some(value) = self.pop() {
+            sorted.push(value);
+        }
+        sorted
+    }
+
+    // Replaces the value of an existing node and moves it up or down into place, returning its new index
+    fn replace(&mut self, index: usize, value: T) -> usize {
+        self.tree[index] = value;
+        let index = self.sift_up(index);
+        self.sift_down(index)
+    }
+}
+
+impl<T, F> BinaryTreeBehavior for Heap<T, F> {}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Sift for Heap<T, F> {
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn is_above(&self, a: usize, b: usize) -> bool {
+        self.kind
+            .is_above((self.compare)(&self.tree[a], &self.tree[b]))
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.tree.swap(i, j);
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Heap<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heap")
+            .field("tree", &self.tree)
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A deterministic mix of values from 1 to 23 with duplicates
+    fn values() -> Vec<u32> {
+        (0..50u32).map(|i| (i * 37 + 11) % 23 + 1).collect()
+    }
+
+    // Checks that no node belongs above its parent
+    fn assert_heap_order<T, F: Fn(&T, &T) -> Ordering>(heap: &Heap<T, F>) {
+        for index in 2..=heap.len() {
+            assert!(!heap.is_above(index, Heap::<T, F>::get_parent(index)));
+        }
+    }
+
+    #[test]
+    fn should_pop_values_in_min_and_max_order() {
+        let mut sorted = values();
+        sorted.sort();
+
+        let mut min = Heap::new(Kind::Min);
+        let mut max = Heap::new(Kind::Max);
+        for value in values() {
+            min.push(value);
+            max.push(value);
+        }
+        assert_eq!(min.len(), 50);
+        assert_eq!(min.peek(), sorted.first());
+        assert_eq!(max.peek(), sorted.last());
+
+        assert_eq!(min.into_sorted_vec(), sorted);
+        sorted.reverse();
+        assert_eq!(max.into_sorted_vec(), sorted);
+    }
+
+    #[test]
+    fn should_heapify_a_vec() {
+        let heap = Heap::from_vec(Kind::Min, values());
+        assert_heap_order(&heap);
+        let mut sorted = values();
+        sorted.sort();
+        assert_eq!(heap.into_sorted_vec(), sorted);
+
+        let mut empty: Heap<u32> = Heap::from_vec(Kind::Max, Vec::new());
+        assert_eq!(empty.peek(), None);
+        assert_eq!(empty.pop(), None);
+    }
+
+    #[test]
+    fn should_order_values_with_a_comparator() {
+        let words = vec!["pear", "fig", "banana", "kiwi", "apple"];
+        let heap = Heap::heapify(Kind::Max, words, |a: &&str, b: &&str| a.len().cmp(&b.len()));
+        assert_eq!(heap.peek(), Some(&"banana"));
+        let lengths: Vec<usize> = heap.into_sorted_vec().iter().map(|w| w.len()).collect();
+        assert_eq!(lengths, [6, 5, 4, 4, 3]);
+    }
+
+    #[test]
+    fn should_move_a_changed_key_into_place() {
+        let mut min = Heap::from_vec(Kind::Min, values());
+        let last = min.len();
+        assert_eq!(min.decrease_key(last, 0), Ok(1));
+        assert_eq!(min.peek(), Some(&0));
+        let index = min.increase_key(1, 100).unwrap();
+        assert_eq!(min.get(index), Some(&100));
+        assert!(Heap::<u32>::get_left_child(index) > min.len());
+        assert_heap_order(&min);
+
+        assert_eq!(min.decrease_key(1, 50), Err(Error::KeyIncreased(1)));
+        assert_eq!(min.increase_key(index, 0), Err(Error::KeyDecreased(index)));
+        assert_eq!(min.increase_key(0, 0), Err(Error::NotANode(0)));
+        assert_eq!(min.decrease_key(51, 0), Err(Error::NotANode(51)));
+
+        // In a max heap a larger key moves towards the top
+        let mut max = Heap::from_vec(Kind::Max, values());
+        assert_eq!(max.increase_key(max.len(), 100), Ok(1));
+        assert_heap_order(&max);
+    }
+}
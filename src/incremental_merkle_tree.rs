@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use crate::hasher::{Hash32, Hasher, HashingMode, Sha3_256};
+use crate::merkle_tree::{Error, MerkleProof};
+
+// An append only tree fills its leaves from left to right, so every node left of the next free leaf is final and every
+// node right of it only holds empty leaves. The frontier keeps, for every level, the last completed node that is a
+// left child, which is all that is needed to hash the next leaf into the tree. Subtrees of empty leaves hash to the
+// zero hash of their level. This is the layout of the Ethereum deposit contract.
+/// An append only merkle tree of fixed depth that stores one hash per level instead of every node
+#[derive(Debug, Clone)]
+pub struct IncrementalMerkleTree<H: Hasher = Sha3_256> {
+    depth: u32,
+    next_index: usize,
+    frontier: Vec<Hash32>,
+    zero_hashes: Vec<Hash32>,
+    witnesses: BTreeMap<usize, Vec<Hash32>>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: Hasher> IncrementalMerkleTree<H> {
+    /// Creates an empty append only merkle tree with room for 2^depth leaves, all of them the empty (zero) hash
+    ///
+    /// Panics if the depth is too large for the leaf indices, see `try_new` for a fallible version
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    ///
+    pub fn new(depth: u32) -> Self {
+        match Self::try_new(depth) {
+            Ok(tree) => tree,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates an empty append only merkle tree with room for 2^depth leaves, returning an error if the depth is too large
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    ///
+    pub fn try_new(depth: u32) -> Result<Self, Error> {
+        let max = usize::BITS - 1;
+        if depth > max {
+            return Err(Error::DepthTooLarge { depth, max });
+        }
+
+        let depth_levels = depth as usize;
+        let mut zero_hashes = vec![Hash32::default(); depth_levels + 1];
+        for level in 0..depth_levels {
+            zero_hashes[level + 1] = H::hash_pair(&zero_hashes[level], &zero_hashes[level]);
+        }
+
+        Ok(IncrementalMerkleTree {
+            depth,
+            next_index: 0,
+            // One more slot than there are levels below the root, the last one holds the root once the tree is full
+            frontier: vec![Hash32::default(); depth_levels + 1],
+            zero_hashes,
+            witnesses: BTreeMap::new(),
+            hasher: PhantomData,
+        })
+    }
+
+    /// Returns the depth of the tree
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the number of leaves the tree has room for
+    pub fn capacity(&self) -> usize {
+        1 << self.depth
+    }
+
+    /// Returns the number of leaves appended so far, which is also the index of the next leaf
+    pub fn leaf_count(&self) -> usize {
+        self.next_index
+    }
+
+    /// Returns true if no leaf has been appended yet
+    pub fn is_empty(&self) -> bool {
+        self.next_index == 0
+    }
+
+    /// Appends a leaf in O(depth) hashes and returns its index among the group of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash to store in the next free leaf
+    ///
+    pub fn append(&mut self, leaf: Hash32) -> Result<usize, Error> {
+        let index = self.next_index;
+        if index >= self.capacity() {
+            return Err(Error::TreeFull(self.capacity()));
+        }
+        let depth = self.depth as usize;
+
+        // The new leaf only changes a single sibling of every witness, the one at the level where their paths meet
+        if !self.witnesses.is_empty() {
+            let mut path = Vec::with_capacity(depth);
+            let mut node = leaf;
+            for level in 0..depth {
+                path.push(node);
+                node = if (index >> level) & 1 == 1 {
+                    H::hash_pair(&self.frontier[level], &node)
+                } else {
+                    H::hash_pair(&node, &self.zero_hashes[level])
+                };
+            }
+            for (marked, siblings) in self.witnesses.iter_mut() {
+                let level = (index ^ marked).ilog2() as usize;
+                siblings[level] = path[level];
+            }
+        }
+
+        // Hash the leaf into completed right children until it is a left child, which becomes the frontier of its level
+        let mut node = leaf;
+        let mut size = index + 1;
+        for level in 0..=depth {
+            if size & 1 == 1 {
+                self.frontier[level] = node;
+                break;
+            }
+            node = H::hash_pair(&self.frontier[level], &node);
+            size >>= 1;
+        }
+
+        self.next_index += 1;
+        Ok(index)
+    }
+
+    /// Returns the root hash of the tree in O(depth) hashes, the leaves that have not been appended yet are empty
+    pub fn root(&self) -> Hash32 {
+        if self.next_index == self.capacity() {
+            return self.frontier[self.depth as usize];
+        }
+        let mut node = self.zero_hashes[0];
+        let mut size = self.next_index;
+        for level in 0..self.depth as usize {
+            node = if size & 1 == 1 {
+                H::hash_pair(&self.frontier[level], &node)
+            } else {
+                H::hash_pair(&node, &self.zero_hashes[level])
+            };
+            size >>= 1;
+        }
+        node
+    }
+
+    /// Starts keeping the proof of the most recently appended leaf up to date, returning its index or None if the tree is empty
+    pub fn mark(&mut self) -> Option<usize> {
+        let index = self.next_index.checked_sub(1)?;
+        // Left siblings are completed nodes on the frontier, right siblings hold no leaves yet
+        let siblings = (0..self.depth as usize)
+            .map(|level| {
+                if (index >> level) & 1 == 1 {
+                    self.frontier[level]
+                } else {
+                    self.zero_hashes[level]
+                }
+            })
+            .collect();
+        self.witnesses.insert(index, siblings);
+        Some(index)
+    }
+
+    /// Stops keeping the proof of a leaf up to date, returning true if it was marked
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn unmark(&mut self, leaf_index: usize) -> bool {
+        self.witnesses.remove(&leaf_index).is_some()
+    }
+
+    /// Returns the indices of the marked leaves in ascending order
+    pub fn marked(&self) -> impl Iterator<Item = usize> + '_ {
+        self.witnesses.keys().copied()
+    }
+
+    /// Returns the proof of a marked leaf against the current root, None if the leaf is not marked
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn witness(&self, leaf_index: usize) -> Option<MerkleProof<H>> {
+        let siblings = self.witnesses.get(&leaf_index)?;
+        let proof = MerkleProof::new(leaf_index, siblings.clone(), HashingMode::Plain)
+            .expect("A witness should have a sibling for every level of the tree");
+        Some(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleTree;
+
+    const DEPTH: u32 = 4;
+
+    fn leaf(i: usize) -> Hash32 {
+        Hash32([i as u8 + 1; 32])
+    }
+
+    // A fully materialized tree holding the first count leaves followed by empty ones
+    fn materialized(count: usize) -> MerkleTree {
+        let leaves = (0..1 << DEPTH).map(|i| {
+            if i < count {
+                leaf(i)
+            } else {
+                Hash32::default()
+            }
+        });
+        MerkleTree::from_leaves(leaves).unwrap()
+    }
+
+    #[test]
+    fn should_match_the_root_of_a_materialized_tree() {
+        let mut tree: IncrementalMerkleTree = IncrementalMerkleTree::new(DEPTH);
+        assert_eq!(tree.root(), *materialized(0).root_hash());
+
+        for i in 0..1 << DEPTH {
+            assert_eq!(tree.append(leaf(i)), Ok(i));
+            assert_eq!(tree.root(), *materialized(i + 1).root_hash());
+        }
+        assert_eq!(tree.leaf_count(), 16);
+    }
+
+    #[test]
+    fn should_refuse_to_append_to_a_full_tree() {
+        let mut tree: IncrementalMerkleTree = IncrementalMerkleTree::new(0);
+        assert_eq!(tree.root(), Hash32::default());
+        assert_eq!(tree.append(leaf(0)), Ok(0));
+        assert_eq!(tree.root(), leaf(0));
+        assert_eq!(tree.append(leaf(1)), Err(Error::TreeFull(1)));
+    }
+
+    #[test]
+    fn should_refuse_depths_larger_than_the_leaf_indices() {
+        assert!(matches!(
+            IncrementalMerkleTree::<Sha3_256>::try_new(usize::BITS),
+            Err(Error::DepthTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn should_keep_the_witnesses_of_marked_leaves_up_to_date() {
+        let mut tree: IncrementalMerkleTree = IncrementalMerkleTree::new(DEPTH);
+        assert_eq!(tree.mark(), None);
+
+        for i in 0..1 << DEPTH {
+            tree.append(leaf(i)).unwrap();
+            if [0, 5, 6, 11].contains(&i) {
+                assert_eq!(tree.mark(), Some(i));
+            }
+
+            let mt = materialized(i + 1);
+            for marked in tree.marked() {
+                let witness = tree.witness(marked).unwrap();
+                assert_eq!(witness, mt.merkle_proof(marked).unwrap());
+                assert!(witness.verify(&leaf(marked), &tree.root()));
+            }
+        }
+        assert_eq!(tree.marked().collect::<Vec<usize>>(), vec![0, 5, 6, 11]);
+    }
+
+    #[test]
+    fn should_stop_tracking_unmarked_leaves() {
+        let mut tree: IncrementalMerkleTree = IncrementalMerkleTree::new(DEPTH);
+        tree.append(leaf(0)).unwrap();
+        tree.mark();
+
+        assert!(tree.unmark(0));
+        assert!(!tree.unmark(0));
+        assert_eq!(tree.witness(0), None);
+    }
+}
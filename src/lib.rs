@@ -1,2 +1,10 @@
 pub mod binary_tree;
+pub mod hasher;
+pub mod heap;
+pub mod incremental_merkle_tree;
+pub mod log_tree;
+pub mod merkle_mountain_range;
 pub mod merkle_tree;
+pub mod priority_queue;
+pub mod sparse_merkle_tree;
+pub mod versioned_merkle_tree;
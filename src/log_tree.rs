@@ -0,0 +1,618 @@
+use std::marker::PhantomData;
+
+use crate::hasher::{Hash32, Hasher, Sha3_256};
+use crate::merkle_tree::Error;
+
+// A log tree holds any number of leaves, as in Certificate Transparency (RFC 9162). The tree of n leaves splits them at
+// the largest power of two k below n, so the left subtree is perfect and only the right one may be incomplete. Every
+// perfect subtree is aligned to its own size, which means it is found among the completed nodes kept for each level,
+// and the hash of any earlier tree size only has to recompute the O(log n) nodes on its incomplete right edge.
+// Leaves and nodes are domain separated with the RFC 6962 prefixes.
+/// An append only merkle tree of any size with inclusion and consistency proofs as specified by RFC 9162
+#[derive(Debug, Clone)]
+pub struct LogTree<H: Hasher = Sha3_256> {
+    levels: Vec<Vec<Hash32>>,
+    hasher: PhantomData<H>,
+}
+
+/// A proof that a leaf is a part of a log tree of a given size, the audit path of RFC 9162
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof<H: Hasher = Sha3_256> {
+    leaf_index: usize,
+    tree_size: usize,
+    hashes: Vec<Hash32>,
+    hasher: PhantomData<H>,
+}
+
+/// A proof that a log tree of an old size is a prefix of the log tree of a new size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof<H: Hasher = Sha3_256> {
+    old_size: usize,
+    new_size: usize,
+    hashes: Vec<Hash32>,
+    hasher: PhantomData<H>,
+}
+
+// Returns the largest power of two smaller than a size of at least two, where the tree of that size is split
+fn split(size: usize) -> usize {
+    1 << (size - 1).ilog2()
+}
+
+impl<H: Hasher> Default for LogTree<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Hasher> LogTree<H> {
+    /// Creates an empty log tree
+    pub fn new() -> Self {
+        LogTree {
+            levels: vec![Vec::new()],
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the number of leaves in the tree
+    pub fn size(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Returns true if no leaf has been appended yet
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Hashes an entry with the leaf prefix and appends it, returning its index among the group of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes of the log entry
+    ///
+    pub fn append(&mut self, data: &[u8]) -> usize {
+        self.append_leaf_hash(H::hash_prefixed_leaf(data))
+    }
+
+    /// Appends the hash of an entry that was already hashed with the leaf prefix, returning its index among the group of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hash` - The leaf hash of the log entry
+    ///
+    pub fn append_leaf_hash(&mut self, leaf_hash: Hash32) -> usize {
+        self.levels[0].push(leaf_hash);
+
+        // Every time a level gets an even number of nodes the last two of them complete a node on the level above
+        let mut level = 0;
+        while self.levels[level].len().is_multiple_of(2) {
+            let nodes = &self.levels[level];
+            let parent = H::hash_prefixed_pair(&nodes[nodes.len() - 2], &nodes[nodes.len() - 1]);
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level + 1].push(parent);
+            level += 1;
+        }
+        self.size() - 1
+    }
+
+    /// Returns the leaf hash of an entry, None if it has not been appended
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn leaf_hash(&self, leaf_index: usize) -> Option<&Hash32> {
+        self.levels[0].get(leaf_index)
+    }
+
+    /// Returns the root hash of the tree, the hash of no data for an empty tree
+    pub fn root(&self) -> Hash32 {
+        self.subtree_hash(0, self.size())
+    }
+
+    /// Returns the root hash the tree had when it held the given number of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `tree_size` - The number of leaves of the earlier tree
+    ///
+    pub fn root_at(&self, tree_size: usize) -> Result<Hash32, Error> {
+        self.check_size(tree_size, 0, self.size())?;
+        Ok(self.subtree_hash(0, tree_size))
+    }
+
+    /// Generates the audit path of a leaf in the tree of the given size
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    /// * `tree_size` - The number of leaves of the tree to prove the leaf against, at most the current size
+    ///
+    pub fn inclusion_proof(
+        &self,
+        leaf_index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof<H>, Error> {
+        self.check_size(tree_size, 1, self.size())?;
+        if leaf_index >= tree_size {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: tree_size,
+            });
+        }
+        Ok(InclusionProof::new(
+            leaf_index,
+            tree_size,
+            self.path(leaf_index, 0, tree_size),
+        ))
+    }
+
+    /// Generates the proof that the tree of the old size is a prefix of the tree of the new size
+    ///
+    /// # Arguments
+    ///
+    /// * `old_size` - The number of leaves of the earlier tree, at least one
+    /// * `new_size` - The number of leaves of the later tree, at most the current size
+    ///
+    pub fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof<H>, Error> {
+        self.check_size(new_size, 1, self.size())?;
+        self.check_size(old_size, 1, new_size)?;
+        Ok(ConsistencyProof::new(
+            old_size,
+            new_size,
+            self.subproof(old_size, 0, new_size, true),
+        ))
+    }
+
+    fn check_size(&self, size: usize, min: usize, max: usize) -> Result<(), Error> {
+        if size < min || size > max {
+            return Err(Error::SizeOutOfRange { size, min, max });
+        }
+        Ok(())
+    }
+
+    // MTH of RFC 9162 for the leaves from start up to end, perfect subtrees are looked up among the completed nodes
+    fn subtree_hash(&self, start: usize, end: usize) -> Hash32 {
+        let size = end - start;
+        if size == 0 {
+            return H::hash(&[]);
+        }
+        if size.is_power_of_two() {
+            let level = size.ilog2() as usize;
+            return self.levels[level][start >> level];
+        }
+        let k = split(size);
+        H::hash_prefixed_pair(
+            &self.subtree_hash(start, start + k),
+            &self.subtree_hash(start + k, end),
+        )
+    }
+
+    // PATH of RFC 9162 for a leaf among the leaves from start up to end
+    fn path(&self, leaf_index: usize, start: usize, end: usize) -> Vec<Hash32> {
+        if end - start == 1 {
+            return Vec::new();
+        }
+        let k = split(end - start);
+        let (mut path, sibling) = if leaf_index < start + k {
+            (
+                self.path(leaf_index, start, start + k),
+                self.subtree_hash(start + k, end),
+            )
+        } else {
+            (
+                self.path(leaf_index, start + k, end),
+                self.subtree_hash(start, start + k),
+            )
+        };
+        path.push(sibling);
+        path
+    }
+
+    // SUBPROOF of RFC 9162 for the old tree ending at old_end among the leaves from start up to end, the flag tells
+    // whether the subtree is the old tree itself, whose hash the verifier already knows
+    fn subproof(&self, old_end: usize, start: usize, end: usize, is_old_tree: bool) -> Vec<Hash32> {
+        if old_end == end {
+            return if is_old_tree {
+                Vec::new()
+            } else {
+                vec![self.subtree_hash(start, end)]
+            };
+        }
+        let k = split(end - start);
+        let (mut proof, sibling) = if old_end <= start + k {
+            (
+                self.subproof(old_end, start, start + k, is_old_tree),
+                self.subtree_hash(start + k, end),
+            )
+        } else {
+            (
+                self.subproof(old_end, start + k, end, false),
+                self.subtree_hash(start, start + k),
+            )
+        };
+        proof.push(sibling);
+        proof
+    }
+}
+
+impl<H: Hasher> InclusionProof<H> {
+    /// Creates an inclusion proof from its parts
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - The index of the proven leaf among the group of leaves
+    /// * `tree_size` - The number of leaves of the tree the proof belongs to
+    /// * `hashes` - The audit path from the leaf level up
+    ///
+    pub fn new(leaf_index: usize, tree_size: usize, hashes: Vec<Hash32>) -> Self {
+        InclusionProof {
+            leaf_index,
+            tree_size,
+            hashes,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the index of the proven leaf among the group of leaves
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Returns the number of leaves of the tree the proof belongs to
+    pub fn tree_size(&self) -> usize {
+        self.tree_size
+    }
+
+    /// Returns the audit path from the leaf level up
+    pub fn hashes(&self) -> &[Hash32] {
+        &self.hashes
+    }
+
+    /// Returns true if the proof shows that the leaf is a part of the tree with the given root, following the
+    /// verification algorithm of RFC 9162
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hash` - The leaf hash of the proven entry
+    /// * `root` - The root hash of the tree at the size of the proof
+    ///
+    pub fn verify(&self, leaf_hash: &Hash32, root: &Hash32) -> bool {
+        if self.leaf_index >= self.tree_size {
+            return false;
+        }
+        let mut node = self.leaf_index;
+        let mut last_node = self.tree_size - 1;
+        let mut computed = *leaf_hash;
+        for hash in &self.hashes {
+            if last_node == 0 {
+                return false;
+            }
+            if node & 1 == 1 || node == last_node {
+                computed = H::hash_prefixed_pair(hash, &computed);
+                // Skip the levels where the node is the last one and has no sibling on the right
+                while node & 1 == 0 && node != 0 {
+                    node >>= 1;
+                    last_node >>= 1;
+                }
+            } else {
+                computed = H::hash_prefixed_pair(&computed, hash);
+            }
+            node >>= 1;
+            last_node >>= 1;
+        }
+        last_node == 0 && computed == *root
+    }
+}
+
+impl<H: Hasher> ConsistencyProof<H> {
+    /// Creates a consistency proof from its parts
+    ///
+    /// # Arguments
+    ///
+    /// * `old_size` - The number of leaves of the earlier tree
+    /// * `new_size` - The number of leaves of the later tree
+    /// * `hashes` - The hashes of the proof as generated by SUBPROOF of RFC 9162
+    ///
+    pub fn new(old_size: usize, new_size: usize, hashes: Vec<Hash32>) -> Self {
+        ConsistencyProof {
+            old_size,
+            new_size,
+            hashes,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the number of leaves of the earlier tree
+    pub fn old_size(&self) -> usize {
+        self.old_size
+    }
+
+    /// Returns the number of leaves of the later tree
+    pub fn new_size(&self) -> usize {
+        self.new_size
+    }
+
+    /// Returns the hashes of the proof
+    pub fn hashes(&self) -> &[Hash32] {
+        &self.hashes
+    }
+
+    /// Returns true if the proof shows that the tree with the old root is a prefix of the tree with the new root,
+    /// following the verification algorithm of RFC 9162
+    ///
+    /// # Arguments
+    ///
+    /// * `old_root` - The root hash of the tree at the old size
+    /// * `new_root` - The root hash of the tree at the new size
+    ///
+    pub fn verify(&self, old_root: &Hash32, new_root: &Hash32) -> bool {
+        if self.old_size == 0 || self.old_size > self.new_size {
+            return false;
+        }
+        if self.old_size == self.new_size {
+            return self.hashes.is_empty() && old_root == new_root;
+        }
+        if self.hashes.is_empty() {
+            return false;
+        }
+
+        // When the old tree is a perfect subtree of the new one its root is left out of the proof
+        let mut hashes = Vec::with_capacity(self.hashes.len() + 1);
+        if self.old_size.is_power_of_two() {
+            hashes.push(*old_root);
+        }
+        hashes.extend_from_slice(&self.hashes);
+
+        let mut node = self.old_size - 1;
+        let mut last_node = self.new_size - 1;
+        while node & 1 == 1 {
+            node >>= 1;
+            last_node >>= 1;
+        }
+
+        // The old root is rebuilt from the left siblings only, the new root from all of them
+        let mut old_computed = hashes[0];
+        let mut new_computed = hashes[0];
+        for hash in &hashes[1..] {
+            if last_node == 0 {
+                return false;
+            }
+            if node & 1 == 1 || node == last_node {
+                old_computed = H::hash_prefixed_pair(hash, &old_computed);
+                new_computed = H::hash_prefixed_pair(hash, &new_computed);
+                while node & 1 == 0 && node != 0 {
+                    node >>= 1;
+                    last_node >>= 1;
+                }
+            } else {
+                new_computed = H::hash_prefixed_pair(&new_computed, hash);
+            }
+            node >>= 1;
+            last_node >>= 1;
+        }
+        last_node == 0 && old_computed == *old_root && new_computed == *new_root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_of(size: usize) -> LogTree {
+        let mut log = LogTree::new();
+        for i in 0..size {
+            log.append(&[i as u8; 3]);
+        }
+        log
+    }
+
+    // MTH of RFC 9162 written out over a list of leaf hashes, without any of the completed nodes
+    fn naive_root(leaves: &[Hash32]) -> Hash32 {
+        match leaves.len() {
+            0 => Sha3_256::hash(&[]),
+            1 => leaves[0],
+            size => {
+                let k = split(size);
+                Sha3_256::hash_prefixed_pair(&naive_root(&leaves[..k]), &naive_root(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn should_split_at_the_largest_power_of_two_below_the_size() {
+        assert_eq!(split(2), 1);
+        assert_eq!(split(5), 4);
+        assert_eq!(split(8), 4);
+        assert_eq!(split(9), 8);
+    }
+
+    #[test]
+    fn should_compute_the_root_of_every_size() {
+        let log = log_of(37);
+        let leaves: Vec<Hash32> = (0..37).map(|i| *log.leaf_hash(i).unwrap()).collect();
+        for size in 0..=37 {
+            assert_eq!(log.root_at(size), Ok(naive_root(&leaves[..size])));
+            assert_eq!(log_of(size).root(), naive_root(&leaves[..size]));
+        }
+        assert_eq!(
+            log.root_at(38),
+            Err(Error::SizeOutOfRange {
+                size: 38,
+                min: 0,
+                max: 37
+            })
+        );
+    }
+
+    #[test]
+    fn should_prove_the_inclusion_of_every_leaf_in_every_size() {
+        let log = log_of(19);
+        for tree_size in 1..=19 {
+            let root = log.root_at(tree_size).unwrap();
+            for leaf_index in 0..tree_size {
+                let leaf_hash = log.leaf_hash(leaf_index).unwrap();
+                let proof = log.inclusion_proof(leaf_index, tree_size).unwrap();
+                assert!(proof.verify(leaf_hash, &root));
+                assert!(!proof.verify(&Hash32::default(), &root));
+                assert!(!proof.verify(leaf_hash, &log.root_at(tree_size - 1).unwrap()));
+            }
+        }
+    }
+
+    #[test]
+    fn should_prove_the_consistency_of_every_pair_of_sizes() {
+        let log = log_of(19);
+        for new_size in 1..=19 {
+            let new_root = log.root_at(new_size).unwrap();
+            for old_size in 1..=new_size {
+                let old_root = log.root_at(old_size).unwrap();
+                let proof = log.consistency_proof(old_size, new_size).unwrap();
+                assert!(proof.verify(&old_root, &new_root));
+                if old_size < new_size {
+                    assert!(!proof.verify(&new_root, &new_root));
+                    assert!(!proof.verify(&old_root, &old_root));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_refuse_consistency_proofs_between_invalid_sizes() {
+        let log = log_of(5);
+        assert_eq!(
+            log.consistency_proof(0, 5),
+            Err(Error::SizeOutOfRange {
+                size: 0,
+                min: 1,
+                max: 5
+            })
+        );
+        assert_eq!(
+            log.consistency_proof(4, 3),
+            Err(Error::SizeOutOfRange {
+                size: 4,
+                min: 1,
+                max: 3
+            })
+        );
+        assert!(log.consistency_proof(3, 6).is_err());
+        assert!(!ConsistencyProof::<Sha3_256>::new(4, 3, vec![log.root()])
+            .verify(&log.root(), &log.root()));
+    }
+
+    #[cfg(feature = "sha256")]
+    mod rfc_9162 {
+        use super::*;
+        use crate::hasher::Sha256;
+
+        const ENTRIES: [&str; 8] = [
+            "",
+            "00",
+            "10",
+            "2021",
+            "3031",
+            "40414243",
+            "5051525354555657",
+            "606162636465666768696a6b6c6d6e6f",
+        ];
+
+        const ROOTS: [&str; 8] = [
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+
+        fn rfc_log() -> LogTree<Sha256> {
+            let mut log = LogTree::new();
+            for entry in ENTRIES {
+                log.append(&hex::decode(entry).unwrap());
+            }
+            log
+        }
+
+        fn hashes(hex: &[&str]) -> Vec<Hash32> {
+            hex.iter().map(|h| Hash32::from_hex(h).unwrap()).collect()
+        }
+
+        #[test]
+        fn should_match_the_roots_of_the_test_vectors() {
+            let log = rfc_log();
+            assert_eq!(
+                LogTree::<Sha256>::new().root().to_hex(),
+                "0xe3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            );
+            for (size, root) in ROOTS.iter().enumerate() {
+                assert_eq!(log.root_at(size + 1), Ok(Hash32::from_hex(root).unwrap()));
+            }
+        }
+
+        #[test]
+        fn should_match_the_inclusion_proofs_of_the_test_vectors() {
+            let log = rfc_log();
+            let proof = log.inclusion_proof(5, 8).unwrap();
+            assert_eq!(
+                proof.hashes(),
+                hashes(&[
+                    "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                    "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+                ])
+            );
+            assert!(proof.verify(log.leaf_hash(5).unwrap(), &log.root()));
+            assert!(log.inclusion_proof(0, 1).unwrap().hashes().is_empty());
+        }
+
+        #[test]
+        fn should_match_the_consistency_proofs_of_the_test_vectors() {
+            let log = rfc_log();
+            let vectors: [(usize, usize, Vec<Hash32>); 4] = [
+                (
+                    1,
+                    8,
+                    hashes(&[
+                        "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                        "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                        "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+                    ]),
+                ),
+                (
+                    6,
+                    8,
+                    hashes(&[
+                        "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+                        "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+                    ]),
+                ),
+                (
+                    2,
+                    5,
+                    hashes(&[
+                        "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                        "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                    ]),
+                ),
+                (8, 8, Vec::new()),
+            ];
+            for (old_size, new_size, expected) in vectors {
+                let proof = log.consistency_proof(old_size, new_size).unwrap();
+                assert_eq!(proof.hashes(), expected);
+                assert!(proof.verify(
+                    &log.root_at(old_size).unwrap(),
+                    &log.root_at(new_size).unwrap()
+                ));
+            }
+        }
+    }
+}
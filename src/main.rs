@@ -1 +1,386 @@
-fn main() {}
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+#[cfg(feature = "keccak")]
+use forrest::hasher::Keccak256;
+#[cfg(feature = "sha256")]
+use forrest::hasher::Sha256;
+use forrest::hasher::{Hash32, Hasher, HashingMode, Sha3_256};
+use forrest::merkle_tree::{MerkleProof, MerkleTree};
+
+const USAGE: &str = "\
+Usage: forrest <command> [options]
+
+Commands:
+  build [FILE]                  Build a tree from the leaves and print its root
+  proof [FILE] --index N        Print the proof of the leaf at index N
+  verify PROOF_FILE --root HASH Check a proof printed by the proof command against a root
+  inspect [FILE]                Print every level of the tree from the root down
+
+Leaves are read from FILE, or from stdin when it is missing or -, as one hexadecimal 32 byte hash per line. The
+leaves are padded with zero hashes up to the next power of two. Every command prints a single JSON object.
+
+Options:
+  --mode plain|rfc6962|sorted-pair   How leaves and intermediate nodes are hashed, plain by default
+  --hash sha3-256|keccak256|sha256   The hash function, sha3-256 by default
+  -h, --help                         Print this help
+
+Exit codes: 0 on success, 1 if a proof does not verify, 2 on invalid arguments, 3 on invalid input";
+
+// The exit codes scripts can tell failures apart by, 0 is success
+const EXIT_INVALID_PROOF: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_INVALID_INPUT: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Build,
+    Proof,
+    Verify,
+    Inspect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashFunction {
+    Sha3_256,
+    #[cfg(feature = "keccak")]
+    Keccak256,
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+// The parsed command line
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    command: Command,
+    input: Option<String>,
+    mode: HashingMode,
+    hash: HashFunction,
+    index: Option<usize>,
+    root: Option<Hash32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CliError {
+    // The arguments do not make up a valid command, the usage is printed along with the message
+    Usage(String),
+    // The command could not be carried out on the given input
+    InvalidInput(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::InvalidInput(_) => EXIT_INVALID_INPUT,
+        }
+    }
+}
+
+impl From<forrest::merkle_tree::Error> for CliError {
+    fn from(err: forrest::merkle_tree::Error) -> Self {
+        CliError::InvalidInput(err.to_string())
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        CliError::InvalidInput(err.to_string())
+    }
+}
+
+// The proof of a leaf as printed by the proof command and read by the verify command
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(bound = "")]
+struct LeafProof<H: Hasher> {
+    leaf: Hash32,
+    proof: MerkleProof<H>,
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, CliError> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("build") => Command::Build,
+        Some("proof") => Command::Proof,
+        Some("verify") => Command::Verify,
+        Some("inspect") => Command::Inspect,
+        Some("-h" | "--help") => return Ok(None),
+        Some(other) => return Err(CliError::Usage(format!("unknown command {}", other))),
+        None => return Err(CliError::Usage(String::from("missing command"))),
+    };
+
+    let mut options = Options {
+        command,
+        input: None,
+        mode: HashingMode::Plain,
+        hash: HashFunction::Sha3_256,
+        index: None,
+        root: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| CliError::Usage(format!("missing value of {}", arg)))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--mode" => {
+                options.mode = match value()?.as_str() {
+                    "plain" => HashingMode::Plain,
+                    "rfc6962" => HashingMode::Rfc6962,
+                    "sorted-pair" => HashingMode::SortedPair,
+                    other => return Err(CliError::Usage(format!("unknown mode {}", other))),
+                }
+            }
+            "--hash" => {
+                options.hash = match value()?.as_str() {
+                    "sha3-256" => HashFunction::Sha3_256,
+                    #[cfg(feature = "keccak")]
+                    "keccak256" => HashFunction::Keccak256,
+                    #[cfg(feature = "sha256")]
+                    "sha256" => HashFunction::Sha256,
+                    other => {
+                        return Err(CliError::Usage(format!(
+                            "unknown or disabled hash function {}",
+                            other
+                        )))
+                    }
+                }
+            }
+            "--index" => {
+                let index = value()?;
+                options.index = Some(index.parse().map_err(|_| {
+                    CliError::Usage(format!("index {} is not a non negative integer", index))
+                })?);
+            }
+            "--root" => {
+                let root = value()?;
+                options.root = Some(Hash32::from_hex(&root).map_err(|err| {
+                    CliError::Usage(format!("root {} is not a valid hash: {}", root, err))
+                })?);
+            }
+            flag if flag.starts_with("--") => {
+                return Err(CliError::Usage(format!("unknown option {}", flag)))
+            }
+            _ if options.input.is_none() => options.input = Some(arg),
+            _ => return Err(CliError::Usage(format!("unexpected argument {}", arg))),
+        }
+    }
+
+    match options.command {
+        Command::Proof if options.index.is_none() => {
+            Err(CliError::Usage(String::from("proof needs --index")))
+        }
+        Command::Verify if options.input.is_none() || options.root.is_none() => Err(
+            CliError::Usage(String::from("verify needs a proof file and --root")),
+        ),
+        _ => Ok(Some(options)),
+    }
+}
+
+// Reads the whole input file, or stdin if there is none
+fn read_input(input: &Option<String>) -> Result<String, CliError> {
+    match input.as_deref() {
+        None | Some("-") => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            Ok(text)
+        }
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| CliError::InvalidInput(format!("{}: {}", path, err))),
+    }
+}
+
+// Decodes one leaf hash per line, skipping blank lines
+fn parse_leaves(text: &str) -> Result<Vec<Hash32>, CliError> {
+    let leaves = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            Hash32::from_hex(line.trim())
+                .map_err(|err| CliError::InvalidInput(format!("line {}: {}", number + 1, err)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if leaves.is_empty() {
+        return Err(CliError::InvalidInput(String::from("no leaves given")));
+    }
+    Ok(leaves)
+}
+
+// Runs a command with the given input and writes its JSON output, returning the exit code
+fn run<H: Hasher, W: Write>(options: &Options, input: &str, out: &mut W) -> Result<u8, CliError> {
+    if options.command == Command::Verify {
+        let LeafProof { leaf, proof } = serde_json::from_str::<LeafProof<H>>(input)
+            .map_err(|err| CliError::InvalidInput(format!("invalid proof file: {}", err)))?;
+        let root = options
+            .root
+            .expect("Verify is only parsed along with a root");
+        let valid = proof.verify(&leaf, &root);
+        let output =
+            serde_json::json!({ "valid": valid, "computed_root": proof.compute_root(&leaf) });
+        writeln!(out, "{}", output)?;
+        return Ok(if valid { 0 } else { EXIT_INVALID_PROOF });
+    }
+
+    let leaves = parse_leaves(input)?;
+    let mt: MerkleTree<H> =
+        MerkleTree::from_leaves_with_mode(leaves.iter().copied(), Hash32::default(), options.mode)?;
+    let output = match options.command {
+        Command::Build => serde_json::json!({
+            "root": mt.root_hash(),
+            "depth": mt.depth(),
+            "leaf_count": leaves.len(),
+            "mode": mt.mode(),
+        }),
+        Command::Proof => {
+            let index = options
+                .index
+                .expect("Proof is only parsed along with an index");
+            let proof = mt.merkle_proof(index)?;
+            let leaf = leaves.get(index).copied().unwrap_or_default();
+            serde_json::to_value(LeafProof { leaf, proof })
+                .expect("A proof should always serialize to JSON")
+        }
+        Command::Inspect => {
+            // The nodes of each level are the indices from 2^level up to 2^(level+1)
+            let levels: Vec<Vec<&Hash32>> = (0..=mt.depth())
+                .map(|level| {
+                    ((1 << level)..(2 << level))
+                        .map(|index| mt.get(index))
+                        .collect()
+                })
+                .collect();
+            serde_json::json!({ "depth": mt.depth(), "mode": mt.mode(), "levels": levels })
+        }
+        Command::Verify => unreachable!("Verify returns before the tree is built"),
+    };
+    writeln!(out, "{}", output)?;
+    Ok(0)
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(|options| {
+        let Some(options) = options else {
+            println!("{}", USAGE);
+            return Ok(0);
+        };
+        let input = read_input(&options.input)?;
+        let mut out = io::stdout().lock();
+        match options.hash {
+            HashFunction::Sha3_256 => run::<Sha3_256, _>(&options, &input, &mut out),
+            #[cfg(feature = "keccak")]
+            HashFunction::Keccak256 => run::<Keccak256, _>(&options, &input, &mut out),
+            #[cfg(feature = "sha256")]
+            HashFunction::Sha256 => run::<Sha256, _>(&options, &input, &mut out),
+        }
+    });
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            match &err {
+                CliError::Usage(message) => eprintln!("forrest: {}\n\n{}", message, USAGE),
+                CliError::InvalidInput(message) => eprintln!("forrest: {}", message),
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn leaves() -> String {
+        (0..5)
+            .map(|i| format!("{}\n", Hash32([i as u8 * 0x11; 32])))
+            .collect()
+    }
+
+    fn run_command(line: &str, input: &str) -> (Result<u8, CliError>, serde_json::Value) {
+        let options = parse_args(args(line)).unwrap().unwrap();
+        let mut out = Vec::new();
+        let result = run::<Sha3_256, _>(&options, input, &mut out);
+        let output = serde_json::from_slice(&out).unwrap_or(serde_json::Value::Null);
+        (result, output)
+    }
+
+    #[test]
+    fn should_parse_the_options_of_a_command() {
+        let options = parse_args(args("proof leaves.txt --mode rfc6962 --index 3"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.command, Command::Proof);
+        assert_eq!(options.input.as_deref(), Some("leaves.txt"));
+        assert_eq!(options.mode, HashingMode::Rfc6962);
+        assert_eq!(options.index, Some(3));
+        assert_eq!(parse_args(args("build --help")), Ok(None));
+
+        for invalid in [
+            "",
+            "plant",
+            "build --mode fancy",
+            "proof",
+            "verify proof.json",
+            "build a b",
+            "build --index",
+        ] {
+            let err = parse_args(args(invalid)).unwrap_err();
+            assert_eq!(err.exit_code(), EXIT_USAGE, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn should_build_the_same_root_as_the_library() {
+        let mt: MerkleTree = MerkleTree::from_leaves(parse_leaves(&leaves()).unwrap()).unwrap();
+        let (result, output) = run_command("build", &leaves());
+        assert_eq!(result, Ok(0));
+        assert_eq!(output["root"], mt.root_hash().to_hex());
+        assert_eq!(output["depth"], 3);
+        assert_eq!(output["leaf_count"], 5);
+
+        let (_, output) = run_command("inspect", &leaves());
+        assert_eq!(output["levels"][0][0], mt.root_hash().to_hex());
+        assert_eq!(output["levels"][3].as_array().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn should_verify_a_proof_it_printed() {
+        let (_, output) = run_command("proof --mode sorted-pair --index 4", &leaves());
+        let proof = output.to_string();
+        let (_, output) = run_command("build --mode sorted-pair", &leaves());
+        let root = output["root"].as_str().unwrap().to_string();
+
+        let (result, output) = run_command(&format!("verify proof.json --root {}", root), &proof);
+        assert_eq!(result, Ok(0));
+        assert_eq!(output["valid"], true);
+
+        let other_root = Hash32([0xff; 32]);
+        let (result, output) =
+            run_command(&format!("verify proof.json --root {}", other_root), &proof);
+        assert_eq!(result, Ok(EXIT_INVALID_PROOF));
+        assert_eq!(output["valid"], false);
+    }
+
+    #[test]
+    fn should_report_invalid_input() {
+        let (result, _) = run_command("build", "0x1234\n");
+        assert_eq!(result.unwrap_err().exit_code(), EXIT_INVALID_INPUT);
+        let (result, _) = run_command("build", "\n\n");
+        assert_eq!(
+            result.unwrap_err(),
+            CliError::InvalidInput(String::from("no leaves given"))
+        );
+        let (result, _) = run_command("proof --index 8", &leaves());
+        assert_eq!(result.unwrap_err().exit_code(), EXIT_INVALID_INPUT);
+        let root = Hash32::default();
+        let (result, _) = run_command(&format!("verify proof.json --root {}", root), "{}");
+        assert_eq!(result.unwrap_err().exit_code(), EXIT_INVALID_INPUT);
+    }
+}
@@ -0,0 +1,421 @@
+use std::marker::PhantomData;
+
+use crate::binary_tree::BinaryTreeBehavior;
+use crate::hasher::{Hash32, Hasher, Sha3_256};
+use crate::merkle_tree::Error;
+
+// A merkle mountain range splits its leaves into perfect binary trees whose sizes are the powers of two that make up
+// the number of leaves, from the largest on the left to the smallest on the right. The roots of these trees are the
+// peaks, and appending a leaf adds a peak of a single leaf that is merged with its left neighbour for as long as the
+// two have the same height. The range of any earlier size is made up of aligned perfect subtrees of today's peaks,
+// which is why proofs can be generated for every historical size.
+/// An append only accumulator of any size, made up of perfect merkle trees whose peaks are bagged into a single root
+#[derive(Debug, Clone)]
+pub struct MerkleMountainRange<H: Hasher = Sha3_256> {
+    peaks: Vec<Peak>,
+    leaf_count: usize,
+    hasher: PhantomData<H>,
+}
+
+// A perfect binary tree stored with the same one based index calculus as MerkleTree, index 0 is left unused
+#[derive(Debug, Clone)]
+struct Peak {
+    nodes: Vec<Hash32>,
+}
+
+/// A proof that a leaf is a part of a merkle mountain range of a given size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrProof<H: Hasher = Sha3_256> {
+    leaf_index: usize,
+    mmr_size: usize,
+    siblings: Vec<Hash32>,
+    peaks: Vec<Hash32>,
+    hasher: PhantomData<H>,
+}
+
+impl BinaryTreeBehavior for Peak {}
+
+impl Peak {
+    fn height(&self) -> u32 {
+        (self.nodes.len() / 2).ilog2()
+    }
+
+    fn root(&self) -> &Hash32 {
+        &self.nodes[1]
+    }
+
+    // Returns the node at a depth below the peak, with the offset counted from the left of that depth
+    fn node(&self, depth: u32, offset: usize) -> &Hash32 {
+        &self.nodes[Self::get_node_index(depth, offset as u32) as usize]
+    }
+
+    // Places two peaks of the same height below a new root, every depth of the two becomes the left and the right
+    // half of the depth below it in the merged peak
+    fn merge<H: Hasher>(left: Peak, right: Peak) -> Peak {
+        let height = left.height();
+        let mut nodes = vec![Hash32::default(); 2 * left.nodes.len()];
+        nodes[1] = H::hash_pair(left.root(), right.root());
+        for depth in 0..=height {
+            let start = Self::get_node_index(depth, 0) as usize;
+            let end = Self::get_node_index(depth + 1, 0) as usize;
+            let merged_start = Self::get_node_index(depth + 1, 0) as usize;
+            let width = end - start;
+            nodes[merged_start..merged_start + width].copy_from_slice(&left.nodes[start..end]);
+            nodes[merged_start + width..merged_start + 2 * width]
+                .copy_from_slice(&right.nodes[start..end]);
+        }
+        Peak { nodes }
+    }
+}
+
+// Returns the height of each peak of a range of the given size and the index of its first leaf, from left to right
+fn peak_layout(size: usize) -> Vec<(u32, usize)> {
+    let mut start = 0;
+    (0..usize::BITS)
+        .rev()
+        .filter(|height| (size >> height) & 1 == 1)
+        .map(|height| {
+            let peak = (height, start);
+            start += 1 << height;
+            peak
+        })
+        .collect()
+}
+
+// Bags the peaks from right to left, so the rightmost peaks are hashed together first
+fn bag_peaks<H: Hasher>(peaks: &[Hash32]) -> Option<Hash32> {
+    let (last, rest) = peaks.split_last()?;
+    Some(
+        rest.iter()
+            .rev()
+            .fold(*last, |root, peak| H::hash_pair(peak, &root)),
+    )
+}
+
+impl<H: Hasher> Default for MerkleMountainRange<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Hasher> MerkleMountainRange<H> {
+    /// Creates an empty merkle mountain range
+    pub fn new() -> Self {
+        MerkleMountainRange {
+            peaks: Vec::new(),
+            leaf_count: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the number of leaves appended so far
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Returns true if no leaf has been appended yet
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Appends a leaf, merging peaks of the same height, and returns its index among the group of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash to append
+    ///
+    pub fn append(&mut self, leaf: Hash32) -> usize {
+        let mut peak = Peak {
+            nodes: vec![Hash32::default(), leaf],
+        };
+        while self.peaks.last().map(Peak::height) == Some(peak.height()) {
+            let left = self.peaks.pop().unwrap();
+            peak = Peak::merge::<H>(left, peak);
+        }
+        self.peaks.push(peak);
+        self.leaf_count += 1;
+        self.leaf_count - 1
+    }
+
+    /// Returns the hash of a leaf, None if it has not been appended
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn get(&self, leaf_index: usize) -> Option<&Hash32> {
+        (leaf_index < self.leaf_count).then(|| self.node(0, leaf_index))
+    }
+
+    /// Returns the current peak hashes from left to right
+    pub fn peaks(&self) -> Vec<Hash32> {
+        self.peaks.iter().map(|peak| *peak.root()).collect()
+    }
+
+    /// Returns the root hash that bags all of the current peaks, None if the range is empty
+    pub fn root(&self) -> Option<Hash32> {
+        self.root_at(self.leaf_count)
+    }
+
+    /// Returns the root hash the range had when it held the given number of leaves, None if it never had that size
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of leaves of the earlier range
+    ///
+    pub fn root_at(&self, size: usize) -> Option<Hash32> {
+        if size > self.leaf_count {
+            return None;
+        }
+        bag_peaks::<H>(&self.peaks_at(size))
+    }
+
+    /// Generates the proof of a leaf against the current root
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn proof(&self, leaf_index: usize) -> Result<MmrProof<H>, Error> {
+        self.proof_at(leaf_index, self.leaf_count)
+    }
+
+    /// Generates the proof of a leaf against the root the range had when it held the given number of leaves
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    /// * `size` - The number of leaves of the earlier range, which has to include the leaf
+    ///
+    pub fn proof_at(&self, leaf_index: usize, size: usize) -> Result<MmrProof<H>, Error> {
+        if size > self.leaf_count {
+            return Err(Error::SizeOutOfRange {
+                size,
+                min: 0,
+                max: self.leaf_count,
+            });
+        }
+        if leaf_index >= size {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: size,
+            });
+        }
+
+        let layout = peak_layout(size);
+        let position = MmrProof::<H>::peak_position(&layout, leaf_index);
+        let (height, _) = layout[position];
+        let siblings = (0..height)
+            .map(|level| *self.node(level, (leaf_index >> level) ^ 1))
+            .collect();
+        let mut peaks = self.peaks_at(size);
+        peaks.remove(position);
+
+        Ok(MmrProof {
+            leaf_index,
+            mmr_size: size,
+            siblings,
+            peaks,
+            hasher: PhantomData,
+        })
+    }
+
+    // Returns the peak hashes the range had when it held the given number of leaves
+    fn peaks_at(&self, size: usize) -> Vec<Hash32> {
+        peak_layout(size)
+            .into_iter()
+            .map(|(height, start)| *self.node(height, start >> height))
+            .collect()
+    }
+
+    // Returns the root of the aligned perfect subtree of the given height holding the leaves from position * 2^height on,
+    // which lies within a single current peak as long as all of its leaves have been appended
+    fn node(&self, height: u32, position: usize) -> &Hash32 {
+        let first_leaf = position << height;
+        let mut start = 0;
+        for peak in &self.peaks {
+            let peak_height = peak.height();
+            if first_leaf < start + (1 << peak_height) {
+                return peak.node(peak_height - height, (first_leaf - start) >> height);
+            }
+            start += 1 << peak_height;
+        }
+        unreachable!("Subtrees are only looked up within the appended leaves")
+    }
+}
+
+impl<H: Hasher> MmrProof<H> {
+    // Returns the position among the peaks of the peak holding the leaf
+    fn peak_position(layout: &[(u32, usize)], leaf_index: usize) -> usize {
+        layout
+            .iter()
+            .rposition(|(_, start)| *start <= leaf_index)
+            .expect("The first peak starts at the first leaf")
+    }
+
+    /// Returns the index of the proven leaf among the group of leaves
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Returns the number of leaves of the range the proof belongs to
+    pub fn mmr_size(&self) -> usize {
+        self.mmr_size
+    }
+
+    /// Returns the sibling hashes within the peak of the leaf, from the leaf level up
+    pub fn siblings(&self) -> &[Hash32] {
+        &self.siblings
+    }
+
+    /// Returns the hashes of the other peaks from left to right
+    pub fn peaks(&self) -> &[Hash32] {
+        &self.peaks
+    }
+
+    /// Returns the root hash calculated from a leaf and the proof, None if the proof does not fit the size of the range
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash of the proven leaf
+    ///
+    pub fn compute_root(&self, leaf: &Hash32) -> Option<Hash32> {
+        if self.leaf_index >= self.mmr_size {
+            return None;
+        }
+        let layout = peak_layout(self.mmr_size);
+        let position = Self::peak_position(&layout, self.leaf_index);
+        let (height, start) = layout[position];
+        if self.siblings.len() != height as usize || self.peaks.len() + 1 != layout.len() {
+            return None;
+        }
+
+        // Climb to the peak of the leaf, whether a node is a left or right child follows from its offset in the peak
+        let offset = self.leaf_index - start;
+        let peak = self
+            .siblings
+            .iter()
+            .enumerate()
+            .fold(*leaf, |node, (level, sibling)| {
+                if (offset >> level) & 1 == 1 {
+                    H::hash_pair(sibling, &node)
+                } else {
+                    H::hash_pair(&node, sibling)
+                }
+            });
+
+        let mut peaks = self.peaks.clone();
+        peaks.insert(position, peak);
+        bag_peaks::<H>(&peaks)
+    }
+
+    /// Returns true if the proof shows that the leaf is a part of the range with the given root
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash of the proven leaf
+    /// * `root` - The root hash of the range at the size of the proof
+    ///
+    pub fn verify(&self, leaf: &Hash32, root: &Hash32) -> bool {
+        self.compute_root(leaf) == Some(*root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleTree;
+
+    fn leaf(i: usize) -> Hash32 {
+        Hash32([i as u8 + 1; 32])
+    }
+
+    fn mmr_of(size: usize) -> MerkleMountainRange {
+        let mut mmr = MerkleMountainRange::new();
+        for i in 0..size {
+            assert_eq!(mmr.append(leaf(i)), i);
+        }
+        mmr
+    }
+
+    #[test]
+    fn should_lay_out_peaks_by_the_bits_of_the_size() {
+        assert_eq!(peak_layout(0), vec![]);
+        assert_eq!(peak_layout(11), vec![(3, 0), (1, 8), (0, 10)]);
+        assert_eq!(mmr_of(11).peaks.len(), 3);
+    }
+
+    #[test]
+    fn should_have_the_root_of_a_merkle_tree_for_a_single_peak() {
+        let mmr = mmr_of(8);
+        let mt: MerkleTree = MerkleTree::from_leaves((0..8).map(leaf)).unwrap();
+        assert_eq!(mmr.peaks(), vec![*mt.root_hash()]);
+        assert_eq!(mmr.root(), Some(*mt.root_hash()));
+    }
+
+    #[test]
+    fn should_bag_the_peaks_from_right_to_left() {
+        let mmr = mmr_of(7);
+        let peaks = mmr.peaks();
+        assert_eq!(
+            peaks,
+            vec![
+                Sha3_256::hash_pair(
+                    &Sha3_256::hash_pair(&leaf(0), &leaf(1)),
+                    &Sha3_256::hash_pair(&leaf(2), &leaf(3))
+                ),
+                Sha3_256::hash_pair(&leaf(4), &leaf(5)),
+                leaf(6)
+            ]
+        );
+        let expected = Sha3_256::hash_pair(&peaks[0], &Sha3_256::hash_pair(&peaks[1], &peaks[2]));
+        assert_eq!(mmr.root(), Some(expected));
+        assert_eq!(mmr.get(6), Some(&leaf(6)));
+        assert_eq!(mmr.get(7), None);
+    }
+
+    #[test]
+    fn should_keep_the_roots_of_every_earlier_size() {
+        let mmr = mmr_of(21);
+        assert_eq!(mmr.root_at(0), None);
+        assert_eq!(mmr.root_at(22), None);
+        for size in 1..=21 {
+            assert_eq!(mmr.root_at(size), mmr_of(size).root());
+        }
+    }
+
+    #[test]
+    fn should_prove_every_leaf_against_every_earlier_size() {
+        let mmr = mmr_of(13);
+        for size in 1..=13 {
+            let root = mmr.root_at(size).unwrap();
+            for leaf_index in 0..size {
+                let proof = mmr.proof_at(leaf_index, size).unwrap();
+                assert_eq!(proof, mmr_of(size).proof(leaf_index).unwrap());
+                assert!(proof.verify(&leaf(leaf_index), &root));
+                assert!(!proof.verify(&leaf(leaf_index + 1), &root));
+                assert!(!proof.verify(&leaf(leaf_index), &mmr.root().unwrap()) || size == 13);
+            }
+        }
+    }
+
+    #[test]
+    fn should_refuse_to_prove_leaves_outside_of_the_size() {
+        let mmr = mmr_of(5);
+        assert_eq!(
+            mmr.proof_at(3, 3),
+            Err(Error::LeafOutOfRange {
+                index: 3,
+                leaf_count: 3
+            })
+        );
+        assert_eq!(
+            mmr.proof_at(0, 6),
+            Err(Error::SizeOutOfRange {
+                size: 6,
+                min: 0,
+                max: 5
+            })
+        );
+    }
+}
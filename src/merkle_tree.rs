@@ -1,14 +1,43 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
 
 use crate::binary_tree::BinaryTreeBehavior;
-use hex::FromHex;
-use hex::{self, FromHexError};
-use sha3::{Digest, Sha3_256};
+use crate::hasher::{Hash32, Hasher, HashingMode, LeafHashing, Sha3_256};
+use hex::FromHexError;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
+mod multiproof;
+mod proof;
+mod snapshot;
+mod storage;
+
+pub use multiproof::MultiProof;
+pub use proof::MerkleProof;
+#[cfg(feature = "mmap")]
+pub use storage::FileStorage;
+pub use storage::Storage;
+
+/// The largest depth a merkle tree can be created with, deeper trees would overflow the index calculus
+pub const MAX_DEPTH: u32 = 30;
+
+/// The length in bytes of the hashes stored in the tree
+pub const HASH_LENGTH: usize = 32;
+
+// The smallest number of nodes a parallel task hashes, below it the work is not worth handing to another thread
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LEN: usize = 1024;
+
+/// A merkle tree whose nodes are combined with the hash function H, SHA3-256 unless specified otherwise, and kept
+/// in the storage S, in memory unless specified otherwise
 #[derive(Debug, Clone)]
-pub struct MerkleTree {
-    depth: u32,
-    representation: Vec<Vec<u8>>,
+pub struct MerkleTree<H: Hasher = Sha3_256, S: Storage = Vec<Hash32>> {
+    representation: S,
+    mode: HashingMode,
+    leaf_hashing: LeafHashing,
+    hasher: PhantomData<H>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,189 +46,583 @@ pub enum Handedness {
     Right,
 }
 
-// A Merkle tree is a special case of a complete binary tree. Therefore, it shares the BinaryTreeBehavior trait
-impl BinaryTreeBehavior for MerkleTree {}
+/// Errors returned by the fallible merkle tree operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A hash could not be decoded from its hexadecimal string representation
+    InvalidHex(FromHexError),
+    /// A hash decoded to a different number of bytes than the tree's hash function produces
+    InvalidHashLength { expected: usize, actual: usize },
+    /// The index does not point to a leaf node of the tree
+    NotALeaf(usize),
+    /// The leaf index is larger than the number of leaves in the tree
+    LeafOutOfRange { index: usize, leaf_count: usize },
+    /// The requested depth is larger than MAX_DEPTH
+    DepthTooLarge { depth: u32, max: u32 },
+    /// Bytes could not be decoded into the expected structure
+    InvalidEncoding(String),
+    /// A node computed while verifying a proof differs from the expected intermediate hash at that level,
+    /// levels are counted up from the leaf
+    PathDiverged {
+        level: usize,
+        expected: Hash32,
+        computed: Hash32,
+    },
+    /// The same leaf was asked to be proven more than once
+    DuplicateLeafIndex(usize),
+    /// A proof does not fit together with the leaves it is supposed to prove
+    MalformedProof(String),
+    /// Every leaf of an append only tree of this capacity has already been filled
+    TreeFull(usize),
+    /// A tree size lies outside of the sizes a log tree has had or that a proof can be given for
+    SizeOutOfRange { size: usize, min: usize, max: usize },
+    /// Reading or writing the storage of a tree failed
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// A stored node does not match the hash of its children, so the tree was changed or damaged outside of this crate
+    CorruptNode(usize),
+    /// A version of a versioned tree was never created or has been pruned
+    UnknownVersion(u64),
+    /// A multiproof was asked for without any leaves, which could never be verified
+    NoLeavesToProve,
+}
 
-impl MerkleTree {
-    fn hex_to_bytes(s: String) -> Result<Vec<u8>, FromHexError> {
-        let without_prefix = if s.starts_with("0x") {
-            String::from(&s[2..s.len()])
-        } else {
-            s
-        };
-        Vec::<u8>::from_hex(without_prefix)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidHex(err) => write!(f, "invalid hexadecimal hash: {}", err),
+            Error::InvalidHashLength { expected, actual } => write!(
+                f,
+                "invalid hash length: expected {} bytes, got {}",
+                expected, actual
+            ),
+            Error::NotALeaf(index) => write!(f, "node at index {} is not a leaf", index),
+            Error::LeafOutOfRange { index, leaf_count } => write!(
+                f,
+                "leaf index {} is out of range for a tree with {} leaves",
+                index, leaf_count
+            ),
+            Error::DepthTooLarge { depth, max } => {
+                write!(
+                    f,
+                    "depth {} is larger than the maximum depth {}",
+                    depth, max
+                )
+            }
+            Error::InvalidEncoding(reason) => write!(f, "invalid encoding: {}", reason),
+            Error::PathDiverged {
+                level,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "proof path diverged {} levels above the leaf: expected {}, computed {}",
+                level, expected, computed
+            ),
+            Error::DuplicateLeafIndex(index) => {
+                write!(f, "leaf index {} is proven more than once", index)
+            }
+            Error::MalformedProof(reason) => write!(f, "malformed proof: {}", reason),
+            Error::TreeFull(capacity) => {
+                write!(f, "tree is full, all {} leaves are taken", capacity)
+            }
+            Error::SizeOutOfRange { size, min, max } => write!(
+                f,
+                "tree size {} is outside of the range {} to {}",
+                size, min, max
+            ),
+            Error::Io { message, .. } => write!(f, "storage error: {}", message),
+            Error::CorruptNode(index) => write!(
+                f,
+                "node at index {} does not match the hash of its children",
+                index
+            ),
+            Error::UnknownVersion(version) => {
+                write!(f, "version {} does not exist or has been pruned", version)
+            }
+            Error::NoLeavesToProve => write!(f, "a multiproof needs at least one leaf to prove"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidHex(err) => Some(err),
+            _ => None,
+        }
     }
+}
 
-    fn concatenate_hashes(left: Vec<u8>, right: Vec<u8>) -> Vec<u8> {
-        let mut concatenation = left;
-        let mut right_vec = right;
-        concatenation.append(&mut right_vec);
-        return concatenation;
+impl From<FromHexError> for Error {
+    fn from(err: FromHexError) -> Self {
+        Error::InvalidHex(err)
     }
+}
 
-    fn hash(v: Vec<u8>) -> Vec<u8> {
-        let mut hasher = Sha3_256::new();
-        hasher.update(v.clone());
-        let hashed: Vec<u8> = hasher.finalize().to_vec();
-        return hashed;
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+// A Merkle tree is a special case of a complete binary tree. Therefore, it shares the BinaryTreeBehavior trait
+impl<H: Hasher, S: Storage> BinaryTreeBehavior for MerkleTree<H, S> {}
+
+impl<H: Hasher> MerkleTree<H> {
+    // The depth counts the layers below the root, so a tree of the given depth holds 2^depth leaves
+    fn leaf_count_for_depth(depth: u32) -> usize {
+        1 << depth
     }
 
     // Exercise 3:
     /// Creates a merkle tree of depth and initializez its leaves to the initial leaf value
     ///
+    /// The depth is the number of layers below the root, a tree of depth d has 2^d leaves
+    ///
+    /// Panics if the initial leaf is not a valid hash or the depth is too large, see `try_new` for a fallible version
+    ///
     /// # Arguments
     ///
     /// * `depth` - An integer indicating the depth of the tree
     /// * `initial_leaf` - A string representation of a hexadecimal hash to be used as an initialization value for all of the tree's leaf nodes
     ///
     pub fn new(depth: u32, initial_leaf: String) -> Self {
-        // Handle edge cases
-        if depth == 0 {
-            return MerkleTree {
-                depth: depth,
-                representation: vec![
-                    Vec::new(),
-                    Self::hex_to_bytes(initial_leaf)
-                        .expect("Initial leaf should be a hexadecimal string"),
-                ],
-            };
-        }
-        if depth == 1 {
-            let left = Self::hex_to_bytes(initial_leaf.clone())
-                .expect("Initial leaf should be a hexadecimal string");
-            let right = Self::hex_to_bytes(initial_leaf)
-                .expect("Initial leaf should be a hexadecimal string");
-            return MerkleTree {
-                depth: depth,
-                representation: vec![
-                    Vec::new(),
-                    Self::hash(Self::concatenate_hashes(left.clone(), right.clone())),
-                    left,
-                    right,
-                ],
-            };
+        match Self::try_new(depth, initial_leaf) {
+            Ok(mt) => mt,
+            Err(err @ Error::DepthTooLarge { .. }) => panic!("{}", err),
+            Err(_) => panic!("Initial leaf should be a hexadecimal string"),
         }
+    }
 
-        let base: u32 = 2;
-        let mut mt: MerkleTree = MerkleTree {
-            depth,
-            representation: vec![Vec::new(); base.pow(depth) as usize],
-        };
+    /// Creates a merkle tree of depth and initializes its leaves to the initial leaf value, returning an error instead of panicking on bad input
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `initial_leaf` - A string representation of a hexadecimal hash to be used as an initialization value for all of the tree's leaf nodes
+    ///
+    pub fn try_new(depth: u32, initial_leaf: String) -> Result<Self, Error> {
+        Self::try_new_with_mode(depth, initial_leaf, HashingMode::Plain)
+    }
 
-        let as_bytes = Self::hex_to_bytes(initial_leaf.clone())
-            .expect("Initial leaf should be a hexadecimal string");
+    /// Creates a merkle tree of depth whose leaves and intermediate nodes are hashed according to the hashing mode
+    ///
+    /// Panics if the initial leaf is not a valid hash or the depth is too large, see `try_new_with_mode` for a fallible version
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `initial_leaf` - A string representation of a hexadecimal hash to be used as an initialization value for all of the tree's leaf nodes
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn new_with_mode(depth: u32, initial_leaf: String, mode: HashingMode) -> Self {
+        match Self::try_new_with_mode(depth, initial_leaf, mode) {
+            Ok(mt) => mt,
+            Err(err @ Error::DepthTooLarge { .. }) => panic!("{}", err),
+            Err(_) => panic!("Initial leaf should be a hexadecimal string"),
+        }
+    }
 
-        // Give all the leafs at the last depth the initial leaf value
-        let start_of_nodes_at_depth = base.pow(depth - 1);
-        for i in (start_of_nodes_at_depth as usize)..mt.representation.len() {
-            mt.representation[i] = as_bytes.clone()
+    /// Creates a merkle tree of depth whose leaves and intermediate nodes are hashed according to the hashing mode, returning an error instead of panicking on bad input
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `initial_leaf` - A string representation of a hexadecimal hash to be used as an initialization value for all of the tree's leaf nodes
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn try_new_with_mode(
+        depth: u32,
+        initial_leaf: String,
+        mode: HashingMode,
+    ) -> Result<Self, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::DepthTooLarge {
+                depth,
+                max: MAX_DEPTH,
+            });
         }
+        let as_hash = Self::hex_to_hash(&initial_leaf)?;
 
-        // Always go one less in depth and compute hashes for those nodes based on their respective children
-        let mut current_depth = depth - 2;
-        while current_depth > 0 {
-            // Go from the start of this depth
-            let start_of_nodes_at_depth = base.pow(current_depth) as usize;
-            let end_of_nodes_at_depth = base.pow(current_depth + 1) as usize;
+        // Every leaf holds the initial leaf value, which is the same as padding an empty list of leaves
+        Ok(Self::build(
+            Vec::new(),
+            Self::leaf_count_for_depth(depth),
+            as_hash,
+            mode,
+        ))
+    }
 
-            let mut seen_hashes: HashMap<String, Vec<u8>> = HashMap::new();
+    /// Creates a merkle tree from a list of leaves in a single bottom up pass, the depth is picked to fit all of the
+    /// leaves and any remaining leaf slots are padded with zero hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The hashes of the leaves from left to right
+    ///
+    pub fn from_leaves<I: IntoIterator<Item = Hash32>>(leaves: I) -> Result<Self, Error> {
+        Self::from_leaves_with_filler(leaves, Hash32::default())
+    }
 
-            for i in start_of_nodes_at_depth..end_of_nodes_at_depth {
-                // retrieve left and right child hash, concatenate together and hash
-                let left_child_hash = mt.representation[MerkleTree::get_left_child(i)].clone();
-                let right_child_hash = mt.representation[MerkleTree::get_right_child(i)].clone();
+    /// Creates a merkle tree from a list of leaves in a single bottom up pass, padding the remaining leaf slots with the filler leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The hashes of the leaves from left to right
+    /// * `filler` - The hash given to the leaf slots that are left over when the number of leaves is not a power of two
+    ///
+    pub fn from_leaves_with_filler<I: IntoIterator<Item = Hash32>>(
+        leaves: I,
+        filler: Hash32,
+    ) -> Result<Self, Error> {
+        Self::from_leaves_with_mode(leaves, filler, HashingMode::Plain)
+    }
 
-                let concatenation = Self::concatenate_hashes(left_child_hash, right_child_hash);
-                let hex_concatenation = hex::encode(concatenation.clone());
+    /// Creates a merkle tree from a list of leaves in a single bottom up pass, hashing its nodes according to the hashing mode
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The hashes of the leaves from left to right
+    /// * `filler` - The hash given to the leaf slots that are left over when the number of leaves is not a power of two
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn from_leaves_with_mode<I: IntoIterator<Item = Hash32>>(
+        leaves: I,
+        filler: Hash32,
+        mode: HashingMode,
+    ) -> Result<Self, Error> {
+        let leaves: Vec<Hash32> = leaves.into_iter().collect();
+        let depth = Self::depth_to_fit(leaves.len())?;
+        Ok(Self::build(
+            leaves,
+            Self::leaf_count_for_depth(depth),
+            filler,
+            mode,
+        ))
+    }
 
-                // Check if you have seen this concatenated has before? use cached hash if you have,
-                // this is going to give us O(depth-1) runtime for creating our example tree of depth = 20
-                let hashed: Vec<u8> =
-                    if let Some(hash) = seen_hashes.get(hex_concatenation.as_str()) {
-                        hash.to_vec()
-                    } else {
-                        let hashed: Vec<u8> = Self::hash(concatenation);
-                        // Place this into the hashmap so we can reuse the sha3 computation later
-                        seen_hashes.insert(hex_concatenation.clone(), hashed.clone());
-                        hashed
-                    };
+    /// Creates a merkle tree from the raw data of its leaves, each of which is hashed with the tree's hash function.
+    /// The depth is picked to fit all of the leaves and any remaining leaf slots are padded with zero hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes of the leaves from left to right
+    ///
+    pub fn from_data<I: IntoIterator<Item = D>, D: AsRef<[u8]>>(data: I) -> Result<Self, Error> {
+        Self::from_data_with_hashing(data, HashingMode::Plain, LeafHashing::Single)
+    }
 
-                mt.representation[i] = hashed;
-            }
-            current_depth = current_depth - 1;
+    /// Creates a merkle tree from the raw data of its leaves, hashing the data according to the leaf hashing and the
+    /// nodes according to the hashing mode
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes of the leaves from left to right
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    /// * `leaf_hashing` - Whether the data of a leaf is hashed once or twice
+    ///
+    pub fn from_data_with_hashing<I: IntoIterator<Item = D>, D: AsRef<[u8]>>(
+        data: I,
+        mode: HashingMode,
+        leaf_hashing: LeafHashing,
+    ) -> Result<Self, Error> {
+        let leaves = data
+            .into_iter()
+            .map(|data| leaf_hashing.hash_leaf::<H>(data.as_ref()));
+        Ok(
+            Self::from_leaves_with_mode(leaves, Hash32::default(), mode)?
+                .with_leaf_hashing(leaf_hashing),
+        )
+    }
+
+    /// Sets how set_data and hash_leaf hash raw leaf data from now on, the nodes already in the tree are not changed
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_hashing` - Whether the data of a leaf is hashed once or twice
+    ///
+    pub fn with_leaf_hashing(mut self, leaf_hashing: LeafHashing) -> Self {
+        self.leaf_hashing = leaf_hashing;
+        self
+    }
+
+    // Picks the smallest depth whose leaf count fits all of the leaves
+    fn depth_to_fit(leaf_count: usize) -> Result<u32, Error> {
+        let depth = leaf_count.next_power_of_two().ilog2();
+        if depth > MAX_DEPTH {
+            return Err(Error::DepthTooLarge {
+                depth,
+                max: MAX_DEPTH,
+            });
+        }
+        Ok(depth)
+    }
+
+    fn build(leaves: Vec<Hash32>, leaf_count: usize, filler: Hash32, mode: HashingMode) -> Self {
+        // All nodes live in a single flat buffer, the leaves occupy its second half and index 0 is left unused
+        let mut representation = vec![Hash32::default(); 2 * leaf_count];
+        Self::build_nodes(&mut representation, &leaves, filler, mode);
+        MerkleTree {
+            representation,
+            mode,
+            leaf_hashing: LeafHashing::Single,
+            hasher: PhantomData,
+        }
+    }
+
+    // Builds the nodes level by level from the leaves up. Every node whose subtree only holds filler leaves has the
+    // same hash, so it is computed once per level instead of once per node. Index 0 is not touched
+    fn build_nodes(nodes: &mut [Hash32], leaves: &[Hash32], filler: Hash32, mode: HashingMode) {
+        let leaf_count = nodes.len() / 2;
+        let mut filled = leaves.len();
+        Self::hash_leaves(mode, &mut nodes[leaf_count..leaf_count + filled], leaves);
+        let mut filler_node = mode.leaf_node::<H>(&filler);
+        nodes[leaf_count + filled..].fill(filler_node);
+
+        // Always go one level up and compute hashes for those nodes based on their respective children
+        let mut start_of_level = leaf_count;
+        while start_of_level > 1 {
+            let start_of_parents = Self::get_parent(start_of_level);
+            filled = filled.div_ceil(2);
+            let (parents, children) = nodes.split_at_mut(start_of_level);
+            Self::hash_level(
+                mode,
+                &mut parents[start_of_parents..start_of_parents + filled],
+                &children[..2 * filled],
+            );
+            filler_node = mode.parent_node::<H>(&filler_node, &filler_node);
+            nodes[start_of_parents + filled..start_of_level].fill(filler_node);
+            start_of_level = start_of_parents;
         }
+    }
 
-        // Calculate the root hash by getting the left and right child of the root node and hashing their concatenated hashes
-        let root_left_child_hash = mt.get(MerkleTree::get_left_child(1));
-        let root_right_child_hash = mt.get(MerkleTree::get_right_child(1));
+    // Stores the leaves as leaf nodes, in parallel chunks with the rayon feature
+    fn hash_leaves(mode: HashingMode, nodes: &mut [Hash32], leaves: &[Hash32]) {
+        #[cfg(feature = "rayon")]
+        nodes
+            .par_iter_mut()
+            .zip(leaves.par_iter())
+            .with_min_len(PARALLEL_MIN_LEN)
+            .for_each(|(node, leaf)| *node = mode.leaf_node::<H>(leaf));
 
-        let concatenation = Self::concatenate_hashes(root_left_child_hash, root_right_child_hash);
+        #[cfg(not(feature = "rayon"))]
+        for (node, leaf) in nodes.iter_mut().zip(leaves) {
+            *node = mode.leaf_node::<H>(leaf);
+        }
+    }
 
-        let hashed: Vec<u8> = Self::hash(concatenation);
+    // Hashes every pair of children into the parent at the same position, in parallel chunks with the rayon feature
+    fn hash_level(mode: HashingMode, parents: &mut [Hash32], children: &[Hash32]) {
+        #[cfg(feature = "rayon")]
+        parents
+            .par_iter_mut()
+            .zip(children.par_chunks_exact(2))
+            .with_min_len(PARALLEL_MIN_LEN)
+            .for_each(|(parent, pair)| *parent = mode.parent_node::<H>(&pair[0], &pair[1]));
 
-        if let Some(elem) = mt.representation.get_mut(1) {
-            *elem = hashed;
+        #[cfg(not(feature = "rayon"))]
+        for (parent, pair) in parents.iter_mut().zip(children.chunks_exact(2)) {
+            *parent = mode.parent_node::<H>(&pair[0], &pair[1]);
         }
+    }
+}
 
-        return mt;
+impl<H: Hasher, S: Storage> MerkleTree<H, S> {
+    // Decodes a hexadecimal string and makes sure it has the length of a hash
+    fn hex_to_hash(s: &str) -> Result<Hash32, Error> {
+        Hash32::from_hex(s)
+    }
+
+    /// Returns the storage holding the nodes of the tree
+    pub fn storage(&self) -> &S {
+        &self.representation
+    }
+
+    /// Writes any changes of the nodes through to the underlying storage
+    pub fn flush(&self) -> Result<(), Error> {
+        Ok(self.representation.flush()?)
     }
 
     /// Returns the root of the tree and converts it into a hexadecimal string representation
     pub fn root(&self) -> String {
-        String::from("0x") + &hex::encode(&self.representation[1])
+        self.representation[1].to_hex()
     }
 
-    pub fn get(&self, index: usize) -> Vec<u8> {
-        self.representation[index].clone()
+    /// Returns the root of the tree as a hash
+    pub fn root_hash(&self) -> &Hash32 {
+        &self.representation[1]
+    }
+
+    /// Returns how the leaves and intermediate nodes of the tree are hashed
+    pub fn mode(&self) -> HashingMode {
+        self.mode
+    }
+
+    /// Returns how raw leaf data is hashed into a leaf
+    pub fn leaf_hashing(&self) -> LeafHashing {
+        self.leaf_hashing
+    }
+
+    /// Returns the leaf hash of raw leaf data as this tree computes it, which is the leaf to verify its proofs with
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes of the leaf
+    ///
+    pub fn hash_leaf<D: AsRef<[u8]>>(&self, data: D) -> Hash32 {
+        self.leaf_hashing.hash_leaf::<H>(data.as_ref())
+    }
+
+    /// Returns the number of layers below the root
+    pub fn depth(&self) -> u32 {
+        self.leaf_count().ilog2()
+    }
+
+    /// Returns the number of leaves, which is always 2^depth
+    pub fn leaf_count(&self) -> usize {
+        self.representation.len() / 2
+    }
+
+    /// Returns the number of nodes in the tree, leaves and root included
+    pub fn node_count(&self) -> usize {
+        self.representation.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> &Hash32 {
+        &self.representation[index]
     }
     pub fn leaf_range(&self) -> std::ops::Range<usize> {
-        let base: u32 = 2;
-        let start_of_nodes_at_depth = base.pow(self.depth - 1);
-        (start_of_nodes_at_depth as usize)..self.representation.len()
+        // The leaves make up the second half of the flat buffer
+        self.leaf_count()..self.representation.len()
     }
 
     pub fn pretty_print(&self) {
         // Print out the merkle tree with the hashes in hex
-        let v: Vec<(usize, String)> = self
-            .representation
-            .iter()
-            .map(|hash| String::from("0x") + &hex::encode(hash))
-            .enumerate()
-            .collect();
-        for (i, v) in v {
-            println!("Index {} and value {}", i, v)
+        for (i, hash) in self.representation.iter().enumerate().skip(1) {
+            println!("Index {} and value {}", i, hash)
         }
     }
 
     // Exercise 4:
     /// Sets a the hash value for a leaf node and rebalances affected nodes inthe merkle tree
     ///
+    /// Panics if the index is not a leaf or the value is not a valid hash, see `try_set` for a fallible version
+    ///
     /// # Arguments
     ///
     /// * `index` - An integer indicating the the index of the leaf node to mutate
     /// * `value` - A hexadecimal string repesenting the hash to be set at this node
     ///
     pub fn set(&mut self, index: usize, value: String) {
+        match self.try_set(index, value) {
+            Ok(()) => {}
+            Err(Error::NotALeaf(_)) => panic!("Attempting to mutate non leaf value"),
+            Err(_) => panic!("Initial leaf should be a hexadecimal string"),
+        }
+    }
+
+    /// Sets the hash value for a leaf node and rebalances affected nodes in the merkle tree, returning an error instead of panicking on bad input
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - An integer indicating the the index of the leaf node to mutate
+    /// * `value` - A hexadecimal string repesenting the hash to be set at this node
+    ///
+    pub fn try_set(&mut self, index: usize, value: String) -> Result<(), Error> {
         // Check if this is a leaf
         if !self.leaf_range().contains(&index) {
-            panic!("Attempting to mutate non leaf value")
+            return Err(Error::NotALeaf(index));
+        }
+
+        self.representation[index] = self.mode.leaf_node::<H>(&Self::hex_to_hash(&value)?);
+        self.rebalance(index);
+        Ok(())
+    }
+
+    /// Hashes raw leaf data with the tree's hash function and sets it as the leaf at leaf_index
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    /// * `data` - The raw bytes of the leaf
+    ///
+    pub fn set_data<D: AsRef<[u8]>>(&mut self, leaf_index: usize, data: D) -> Result<(), Error> {
+        self.set_many(&[(leaf_index, self.hash_leaf(data))])
+    }
+
+    /// Sets the hash values of many leaves and rehashes every affected node once, level by level, so paths that
+    /// overlap are only hashed once. When a leaf is given more than once the last value wins
+    ///
+    /// # Arguments
+    ///
+    /// * `updates` - Pairs of the index of a leaf among the group of leaves and the hash to be set at it
+    ///
+    pub fn set_many(&mut self, updates: &[(usize, Hash32)]) -> Result<(), Error> {
+        // Check every index before writing anything, so a failed batch leaves the tree untouched
+        let leaf_count = self.leaf_count();
+        if let Some((index, _)) = updates.iter().find(|(index, _)| *index >= leaf_count) {
+            return Err(Error::LeafOutOfRange {
+                index: *index,
+                leaf_count,
+            });
         }
 
-        self.representation[index] =
-            Self::hex_to_bytes(value).expect("Initial leaf should be a hexadecimal string");
-        self.rebalance(index)
+        let mut dirty: Vec<usize> = Vec::with_capacity(updates.len());
+        for (leaf_index, hash) in updates {
+            let index = leaf_count + leaf_index;
+            self.representation[index] = self.mode.leaf_node::<H>(hash);
+            dirty.push(index);
+        }
+        dirty.sort_unstable();
+
+        // Siblings share a parent, so every level only holds each dirty parent once after removing the duplicates
+        while dirty.first().is_some_and(|index| *index > 1) {
+            for index in dirty.iter_mut() {
+                *index = Self::get_parent(*index);
+            }
+            dirty.dedup();
+
+            // The dirty parents of a level do not depend on each other, so their hashes can be computed in parallel
+            // with the rayon feature before they are written back
+            let (mode, nodes): (HashingMode, &[Hash32]) = (self.mode, &self.representation);
+            let hash_children = |parent: &usize| {
+                mode.parent_node::<H>(
+                    &nodes[Self::get_left_child(*parent)],
+                    &nodes[Self::get_right_child(*parent)],
+                )
+            };
+            #[cfg(feature = "rayon")]
+            let hashes: Vec<Hash32> = dirty
+                .par_iter()
+                .with_min_len(PARALLEL_MIN_LEN)
+                .map(hash_children)
+                .collect();
+            #[cfg(not(feature = "rayon"))]
+            let hashes: Vec<Hash32> = dirty.iter().map(hash_children).collect();
+
+            for (parent, hash) in dirty.iter().zip(hashes) {
+                self.representation[*parent] = hash;
+            }
+        }
+        Ok(())
     }
 
     pub fn rebalance(&mut self, index: usize) {
         // go all the way to the root and recalculate hashes
         let mut current = index;
-        while current > 0 {
-            let parent = MerkleTree::get_parent(current);
-            let left_child_hash = self.representation[MerkleTree::get_left_child(parent)].clone();
-            let right_child_hash = self.representation[MerkleTree::get_right_child(parent)].clone();
-            let concatenation = Self::concatenate_hashes(left_child_hash, right_child_hash);
-            let hashed: Vec<u8> = Self::hash(concatenation);
-            self.representation[parent] = hashed;
+        while current > 1 {
+            let parent = Self::get_parent(current);
+            let left_child_hash = &self.representation[Self::get_left_child(parent)];
+            let right_child_hash = &self.representation[Self::get_right_child(parent)];
+            self.representation[parent] = self
+                .mode
+                .parent_node::<H>(left_child_hash, right_child_hash);
             current = parent;
         }
     }
@@ -207,17 +630,32 @@ impl MerkleTree {
     // Exercise 5:
     /// Generates the merkle proof path for a given leaf, the sibling_hash part of the path is returned as a hex string for better readability
     ///
+    /// Panics if the leaf index is out of range, see `try_proof` for a fallible version
+    ///
     /// # Arguments
     ///
     /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
     ///
     pub fn proof(&self, leaf_index: usize) -> Vec<(Handedness, String)> {
-        let leaf_index_mapping: HashMap<usize, usize> =
-            self.leaf_range().enumerate().into_iter().collect();
-        let index = leaf_index_mapping
-            .get(&leaf_index)
+        self.try_proof(leaf_index)
             .expect("Leaf index should correspond to an index in the leaf section")
-            .clone();
+    }
+
+    /// Generates the merkle proof path for a given leaf, returning an error if the leaf index is out of range
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn try_proof(&self, leaf_index: usize) -> Result<Vec<(Handedness, String)>, Error> {
+        let leaf_range = self.leaf_range();
+        if leaf_index >= leaf_range.len() {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: leaf_range.len(),
+            });
+        }
+        let index = leaf_range.start + leaf_index;
         // Collect tuples of proof values where the first item of the tuple indicates if the current node is left or right handed
         // And the hash of the sibling
         let mut path: Vec<(Handedness, String)> = Vec::new();
@@ -225,53 +663,238 @@ impl MerkleTree {
         let mut current = index;
         while current > 1 {
             let parent = Self::get_parent(current);
-            let handedness = if current % 2 == 0 {
+            let handedness = if current.is_multiple_of(2) {
                 Handedness::Left
             } else {
                 Handedness::Right
             };
-            let sibling_hash_vec = match handedness {
-                Handedness::Left => {
-                    self.representation[MerkleTree::get_right_child(parent)].clone()
-                }
-                Handedness::Right => {
-                    self.representation[MerkleTree::get_left_child(parent)].clone()
-                }
+            let sibling_hash = match handedness {
+                Handedness::Left => &self.representation[Self::get_right_child(parent)],
+                Handedness::Right => &self.representation[Self::get_left_child(parent)],
             };
-            let sibling_hash_hex = String::from("0x") + &hex::encode(sibling_hash_vec);
 
-            path.push((handedness, sibling_hash_hex));
+            path.push((handedness, sibling_hash.to_hex()));
             current = parent;
         }
-        path
+        Ok(path)
+    }
+
+    /// Generates a typed merkle proof for a given leaf, which can be verified on its own and encoded as bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn merkle_proof(&self, leaf_index: usize) -> Result<MerkleProof<H>, Error> {
+        if leaf_index >= self.leaf_count() {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+        // The sibling of a node is its neighbour at the same level, found by flipping the lowest bit of its index
+        let mut siblings = Vec::with_capacity(self.depth() as usize);
+        let mut current = self.leaf_count() + leaf_index;
+        while current > 1 {
+            siblings.push(self.representation[current ^ 1]);
+            current = Self::get_parent(current);
+        }
+        MerkleProof::new(leaf_index, siblings, self.mode)
+    }
+
+    /// Generates a single proof for many leaves, which holds every sibling hash needed to rebuild the root only once
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_indices` - The indices of the leaves to prove among the group of leaves, in any order, at least one
+    ///
+    pub fn multiproof(&self, leaf_indices: &[usize]) -> Result<MultiProof<H>, Error> {
+        if leaf_indices.is_empty() {
+            return Err(Error::NoLeavesToProve);
+        }
+        let mut sorted_indices = leaf_indices.to_vec();
+        // Nodes are consumed from the highest index down, which keeps the siblings of a level next to each other
+        sorted_indices.sort_unstable_by(|a, b| b.cmp(a));
+        for pair in sorted_indices.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(Error::DuplicateLeafIndex(pair[0]));
+            }
+        }
+        if let Some(&largest) = sorted_indices.first() {
+            if largest >= self.leaf_count() {
+                return Err(Error::LeafOutOfRange {
+                    index: largest,
+                    leaf_count: self.leaf_count(),
+                });
+            }
+        }
+
+        let mut stack: VecDeque<usize> = sorted_indices
+            .iter()
+            .map(|leaf_index| self.leaf_count() + leaf_index)
+            .collect();
+        let mut proof = Vec::new();
+        let mut proof_flags = Vec::new();
+        while let Some(current) = stack.pop_front() {
+            if current == 1 {
+                break;
+            }
+            // The sibling of a node is its neighbour at the same level, found by flipping the lowest bit of its index
+            let sibling = current ^ 1;
+            if stack.front() == Some(&sibling) {
+                // The sibling is proven or computed as well, so it is taken from the queue
+                stack.pop_front();
+                proof_flags.push(true);
+            } else {
+                proof.push(self.representation[sibling]);
+                proof_flags.push(false);
+            }
+            stack.push_back(Self::get_parent(current));
+        }
+
+        MultiProof::new(sorted_indices, self.depth(), self.mode, proof, proof_flags)
     }
+}
 
+impl<H: Hasher> MerkleTree<H> {
     // Exercise 6:
     /// Returns the root hash calculated from a leaf node and its merkle proof path
     ///
+    /// Panics if the leaf or any hash in the path is not a valid hash, see `try_verify` for a fallible version
+    ///
     /// # Arguments
     ///
     /// * `path` - The merkle proof paths to use for testing
     /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
     ///
     pub fn verify(path: Vec<(Handedness, String)>, leaf_hash: String) -> String {
+        Self::try_verify(path, leaf_hash).expect("Proof hashes should be hexadecimal strings")
+    }
+
+    /// Returns the root hash calculated from a leaf node and its merkle proof path, returning an error if any of the hashes can not be decoded
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The merkle proof paths to use for testing
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    ///
+    pub fn try_verify(path: Vec<(Handedness, String)>, leaf_hash: String) -> Result<String, Error> {
+        Self::try_verify_with_mode(path, leaf_hash, HashingMode::Plain)
+    }
+
+    /// Returns whether the merkle proof path leads from the leaf to the expected root, comparing the hashes as bytes so
+    /// that the case of the hexadecimal strings and the 0x prefix do not matter
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - A hexadecimal string representing the expected root hash
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    /// * `path` - The merkle proof path to verify
+    ///
+    pub fn verify_against(
+        root: &str,
+        leaf_hash: &str,
+        path: &[(Handedness, String)],
+    ) -> Result<bool, Error> {
+        Self::verify_against_with_mode(root, leaf_hash, path, HashingMode::Plain)
+    }
+
+    /// Returns whether the merkle proof path leads from the leaf to the expected root in a tree with the given hashing mode
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - A hexadecimal string representing the expected root hash
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    /// * `path` - The merkle proof path to verify
+    /// * `mode` - The hashing mode of the tree the proof was generated from
+    ///
+    pub fn verify_against_with_mode(
+        root: &str,
+        leaf_hash: &str,
+        path: &[(Handedness, String)],
+        mode: HashingMode,
+    ) -> Result<bool, Error> {
+        Self::verify_against_intermediates(root, leaf_hash, path, &[], mode)
+    }
+
+    /// Returns whether the merkle proof path leads from the leaf to the expected root, checking the nodes on the path
+    /// against the expected intermediate hashes on the way. The first level where they diverge is returned as an error
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - A hexadecimal string representing the expected root hash
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    /// * `path` - The merkle proof path to verify
+    /// * `intermediates` - Hexadecimal strings of the expected nodes on the path, starting at the parent of the leaf
+    /// * `mode` - The hashing mode of the tree the proof was generated from
+    ///
+    pub fn verify_against_intermediates(
+        root: &str,
+        leaf_hash: &str,
+        path: &[(Handedness, String)],
+        intermediates: &[String],
+        mode: HashingMode,
+    ) -> Result<bool, Error> {
+        let root = Self::hex_to_hash(root)?;
+        let leaf = Self::hex_to_hash(leaf_hash)?;
+        let intermediates = intermediates
+            .iter()
+            .map(|hash| Self::hex_to_hash(hash))
+            .collect::<Result<Vec<Hash32>, Error>>()?;
+        MerkleProof::<H>::from_path(path, mode)?.verify_with_intermediates(
+            &leaf,
+            &root,
+            &intermediates,
+        )
+    }
+
+    /// Returns the root hash calculated from a leaf node and its merkle proof path in a tree with the given hashing mode
+    ///
+    /// Panics if the leaf or any hash in the path is not a valid hash, see `try_verify_with_mode` for a fallible version
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The merkle proof paths to use for testing
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    /// * `mode` - The hashing mode of the tree the proof was generated from
+    ///
+    pub fn verify_with_mode(
+        path: Vec<(Handedness, String)>,
+        leaf_hash: String,
+        mode: HashingMode,
+    ) -> String {
+        Self::try_verify_with_mode(path, leaf_hash, mode)
+            .expect("Proof hashes should be hexadecimal strings")
+    }
+
+    /// Returns the root hash calculated from a leaf node and its merkle proof path in a tree with the given hashing mode, returning an error if any of the hashes can not be decoded
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The merkle proof paths to use for testing
+    /// * `leaf_hash` - A hexadecimal string repesenting the hash at a leaf node
+    /// * `mode` - The hashing mode of the tree the proof was generated from
+    ///
+    pub fn try_verify_with_mode(
+        path: Vec<(Handedness, String)>,
+        leaf_hash: String,
+        mode: HashingMode,
+    ) -> Result<String, Error> {
         // Start with the leaf node hash and then fold over the path in the correct direction
-        path.iter()
-            .fold(leaf_hash.clone(), |acc, (handedness, sibling_hash)| {
-                let hash_bytes_of_current = MerkleTree::hex_to_bytes(acc.clone()).unwrap();
-                let hash_bytes_of_sibling = MerkleTree::hex_to_bytes(sibling_hash.clone()).unwrap();
-                let concatenated: Vec<u8> = match handedness {
-                    Handedness::Left => {
-                        // The current hash should be on the left side of the concatenation
-                        Self::concatenate_hashes(hash_bytes_of_current, hash_bytes_of_sibling)
-                    }
-                    Handedness::Right => {
-                        // The current hash should be on the right side of the concatenation
-                        Self::concatenate_hashes(hash_bytes_of_sibling, hash_bytes_of_current)
-                    }
+        let root = path.iter().try_fold(
+            mode.leaf_node::<H>(&Self::hex_to_hash(&leaf_hash)?),
+            |acc, (handedness, sibling_hash)| {
+                let sibling = Self::hex_to_hash(sibling_hash)?;
+                let hashed = match handedness {
+                    // The current hash should be on the left side of the concatenation
+                    Handedness::Left => mode.parent_node::<H>(&acc, &sibling),
+                    // The current hash should be on the right side of the concatenation
+                    Handedness::Right => mode.parent_node::<H>(&sibling, &acc),
                 };
-                String::from("0x") + &hex::encode(MerkleTree::hash(concatenated))
-            })
+                Ok::<Hash32, Error>(hashed)
+            },
+        )?;
+        Ok(root.to_hex())
     }
 }
 
@@ -289,7 +912,7 @@ mod tests {
         let mt: MerkleTree = MerkleTree::new(20, initial_leaf);
         assert_eq!(
             mt.root(),
-            String::from("0xd4490f4d374ca8a44685fe9471c5b8dbe58cdffd13d30d9aba15dd29efb92930")
+            String::from("0x2e83853566789d4b59a7a6f48e4ee98da67addcb7d194cf75b3bcf5833714d1f")
         );
     }
 
@@ -300,6 +923,128 @@ mod tests {
         let _: MerkleTree = MerkleTree::new(20, initial_leaf);
     }
 
+    #[test]
+    fn should_return_an_error_if_initial_leaf_is_not_hex_format() {
+        let initial_leaf = String::from("Unexpected");
+        let result = MerkleTree::<Sha3_256>::try_new(20, initial_leaf);
+        assert!(matches!(result, Err(Error::InvalidHex(_))));
+    }
+
+    #[test]
+    fn should_return_an_error_if_initial_leaf_has_the_wrong_length() {
+        let initial_leaf = String::from("0xabab");
+        let result = MerkleTree::<Sha3_256>::try_new(5, initial_leaf);
+        assert_eq!(
+            result.unwrap_err(),
+            Error::InvalidHashLength {
+                expected: 32,
+                actual: 2
+            }
+        );
+    }
+
+    #[test]
+    fn should_return_an_error_if_depth_is_too_large() {
+        let initial_leaf =
+            String::from("0xabababababababababababababababababababababababababababababababab");
+        let result = MerkleTree::<Sha3_256>::try_new(MAX_DEPTH + 1, initial_leaf);
+        assert_eq!(
+            result.unwrap_err(),
+            Error::DepthTooLarge {
+                depth: MAX_DEPTH + 1,
+                max: MAX_DEPTH
+            }
+        );
+    }
+
+    #[test]
+    fn should_return_an_error_when_setting_a_non_leaf() {
+        let initial_leaf =
+            String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf.clone());
+        assert_eq!(mt.try_set(1, initial_leaf.clone()), Err(Error::NotALeaf(1)));
+        assert_eq!(
+            mt.try_set(mt.leaf_range().end, initial_leaf),
+            Err(Error::NotALeaf(32))
+        );
+    }
+
+    #[test]
+    fn should_return_an_error_when_setting_a_leaf_to_invalid_hex() {
+        let initial_leaf =
+            String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf);
+        let root = mt.root();
+        let index = mt.leaf_range().start;
+        assert!(matches!(
+            mt.try_set(index, "0xzz".to_owned()),
+            Err(Error::InvalidHex(_))
+        ));
+        assert_eq!(
+            mt.root(),
+            root,
+            "A failed set should leave the tree untouched"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Attempting to mutate non leaf value")]
+    fn should_panic_when_setting_a_non_leaf() {
+        let initial_leaf =
+            String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf.clone());
+        mt.set(1, initial_leaf);
+    }
+
+    #[test]
+    fn should_return_an_error_for_a_proof_of_a_leaf_out_of_range() {
+        let initial_leaf =
+            String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
+        let mt: MerkleTree = MerkleTree::new(4, initial_leaf);
+        assert_eq!(
+            mt.try_proof(16),
+            Err(Error::LeafOutOfRange {
+                index: 16,
+                leaf_count: 16
+            })
+        );
+        assert!(mt.try_proof(15).is_ok());
+    }
+
+    #[test]
+    fn should_return_an_error_when_verifying_a_path_with_invalid_hex() {
+        let path = vec![(Handedness::Left, "not a hash".to_owned())];
+        let result = MerkleTree::<Sha3_256>::try_verify(
+            path,
+            "0x3333333333333333333333333333333333333333333333333333333333333333".to_owned(),
+        );
+        assert!(matches!(result, Err(Error::InvalidHex(_))));
+    }
+
+    #[test]
+    fn should_have_two_to_the_power_of_depth_leaves_for_every_depth() {
+        let initial_leaf =
+            String::from("0xabababababababababababababababababababababababababababababababab");
+        for depth in 0..=24 {
+            let mt: MerkleTree = MerkleTree::new(depth, initial_leaf.clone());
+            assert_eq!(mt.depth(), depth);
+            assert_eq!(mt.leaf_count(), 1 << depth);
+            assert_eq!(mt.leaf_range().len(), mt.leaf_count());
+            assert_eq!(mt.node_count(), 2 * mt.leaf_count() - 1);
+            assert_eq!(mt.leaf_range().end, mt.node_count() + 1);
+        }
+    }
+
+    #[test]
+    fn should_pick_the_smallest_depth_that_fits_the_leaves() {
+        for (leaf_count, depth) in [(0, 0), (1, 0), (2, 1), (3, 2), (4, 2), (5, 3), (17, 5)] {
+            let leaves = vec![Hash32::default(); leaf_count];
+            let mt: MerkleTree = MerkleTree::from_leaves(leaves).unwrap();
+            assert_eq!(mt.depth(), depth);
+            assert_eq!(mt.leaf_range().len(), mt.leaf_count());
+        }
+    }
+
     #[test]
     fn should_create_a_merkle_tree_of_zero_depth_returning_a_root_only_tree() {
         let initial_leaf =
@@ -322,11 +1067,329 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn should_create_a_merkle_tree_hashed_with_keccak_256() {
+        use crate::hasher::Keccak256;
+
+        let initial_leaf =
+            String::from("0xabababababababababababababababababababababababababababababababab");
+        let mt: MerkleTree<Keccak256> = MerkleTree::new(1, initial_leaf);
+        let leaf = Hash32([0xab; 32]);
+        assert_eq!(mt.root(), Keccak256::hash_pair(&leaf, &leaf).to_hex());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn should_create_a_merkle_tree_hashed_with_sha_256_and_verify_its_proofs() {
+        use crate::hasher::Sha256;
+
+        let initial_leaf =
+            String::from("0xabababababababababababababababababababababababababababababababab");
+        let mt: MerkleTree<Sha256> = MerkleTree::new(1, initial_leaf.clone());
+        assert_eq!(
+            mt.root(),
+            String::from("0xec65c8798ecf95902413c40f7b9e6d4b0068885f5f324aba1f9ba1c8e14aea61")
+        );
+        assert_eq!(
+            MerkleTree::<Sha256>::verify(mt.proof(1), initial_leaf),
+            mt.root()
+        );
+    }
+
+    // The root of RFC 6962 over the SHA-256 hashes of the entries of the Certificate Transparency test vectors, since
+    // the tree hashes its 32 byte leaves and not the raw entries
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn should_build_an_rfc_6962_tree_with_the_root_of_the_reference_implementation() {
+        use crate::hasher::Sha256;
+
+        let entries = [
+            "",
+            "00",
+            "10",
+            "2021",
+            "3031",
+            "40414243",
+            "5051525354555657",
+            "606162636465666768696a6b6c6d6e6f",
+        ];
+        let leaves = entries
+            .iter()
+            .map(|entry| Sha256::hash(&hex::decode(entry).unwrap()));
+        let mt: MerkleTree<Sha256> =
+            MerkleTree::from_leaves_with_mode(leaves, Hash32::default(), HashingMode::Rfc6962)
+                .unwrap();
+        assert_eq!(
+            mt.root(),
+            "0x6c0337e5f6ceea8a7dc89c1257f17a8db81e139d8d5329388365d0b008352ef6"
+        );
+    }
+
+    #[test]
+    fn should_create_a_domain_separated_merkle_tree_and_verify_its_proofs() {
+        let initial_leaf =
+            String::from("0xabababababababababababababababababababababababababababababababab");
+        let mut mt: MerkleTree =
+            MerkleTree::new_with_mode(4, initial_leaf.clone(), HashingMode::Rfc6962);
+        let plain: MerkleTree = MerkleTree::new(4, initial_leaf.clone());
+        assert_eq!(mt.mode(), HashingMode::Rfc6962);
+        assert_ne!(mt.root(), plain.root());
+
+        let leaf = Hash32([0xab; 32]);
+        let leaf_node = Sha3_256::hash_prefixed_leaf(leaf.as_ref());
+        assert_eq!(*mt.get(mt.leaf_range().start), leaf_node);
+        assert_eq!(
+            *mt.get(1),
+            (0..4).fold(leaf_node, |node, _| Sha3_256::hash_prefixed_pair(
+                &node, &node
+            ))
+        );
+
+        let value =
+            String::from("0x3333333333333333333333333333333333333333333333333333333333333333");
+        mt.set(mt.leaf_range().start + 3, value.clone());
+        assert_eq!(
+            MerkleTree::<Sha3_256>::verify_with_mode(mt.proof(3), value.clone(), mt.mode()),
+            mt.root()
+        );
+        assert_ne!(
+            MerkleTree::<Sha3_256>::verify(mt.proof(3), value),
+            mt.root(),
+            "A proof should only verify with the hashing mode of its tree"
+        );
+    }
+
+    #[test]
+    fn should_not_accept_an_intermediate_node_as_a_leaf_in_domain_separated_mode() {
+        let initial_leaf =
+            String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
+        for mode in [HashingMode::Plain, HashingMode::Rfc6962] {
+            let mut mt: MerkleTree = MerkleTree::new_with_mode(2, initial_leaf.clone(), mode);
+            mt.set(
+                mt.leaf_range().start + 1,
+                "0x1111111111111111111111111111111111111111111111111111111111111111".to_owned(),
+            );
+            // Present the parent of the first two leaves as if it was a leaf, dropping the lowest level of the path
+            let forged_leaf = mt.get(2).to_hex();
+            let forged_path = mt.proof(0)[1..].to_vec();
+            let forged_root =
+                MerkleTree::<Sha3_256>::verify_with_mode(forged_path, forged_leaf, mode);
+            match mode {
+                HashingMode::Plain => assert_eq!(forged_root, mt.root()),
+                _ => assert_ne!(forged_root, mt.root()),
+            }
+        }
+    }
+
+    fn sequential_leaves(count: usize) -> Vec<Hash32> {
+        (0..count).map(|i| Hash32([i as u8 * 0x11; 32])).collect()
+    }
+
+    #[test]
+    fn should_create_a_merkle_tree_from_a_list_of_leaves() {
+        let mt: MerkleTree = MerkleTree::from_leaves(sequential_leaves(16)).unwrap();
+        assert_eq!(mt.leaf_range().len(), 16);
+        assert_eq!(
+            mt.root(),
+            String::from("0x57054e43fa56333fd51343b09460d48b9204999c376624f52480c5593b91eff4")
+        );
+    }
+
+    #[test]
+    fn should_pad_a_merkle_tree_created_from_leaves_with_the_filler_leaf() {
+        let filler = Hash32([0xab; 32]);
+        let mt: MerkleTree =
+            MerkleTree::from_leaves_with_filler(sequential_leaves(5), filler).unwrap();
+        assert_eq!(mt.leaf_range().len(), 8);
+
+        let mut expected: MerkleTree = MerkleTree::new(3, filler.to_hex());
+        for (i, leaf) in sequential_leaves(5).iter().enumerate() {
+            expected.set(expected.leaf_range().start + i, leaf.to_hex());
+        }
+        assert_eq!(mt.root(), expected.root());
+        for index in mt.leaf_range() {
+            assert_eq!(mt.get(index), expected.get(index));
+        }
+    }
+
+    #[test]
+    fn should_create_a_single_leaf_merkle_tree_from_a_single_or_no_leaves() {
+        let filler = Hash32([0xab; 32]);
+        let mt: MerkleTree = MerkleTree::from_leaves_with_filler(vec![], filler).unwrap();
+        assert_eq!(mt.root(), filler.to_hex());
+
+        let mt: MerkleTree = MerkleTree::from_leaves(sequential_leaves(2)[1..].to_vec()).unwrap();
+        assert_eq!(mt.root(), Hash32([0x11; 32]).to_hex());
+    }
+
+    #[test]
+    fn should_create_a_domain_separated_merkle_tree_from_a_list_of_leaves() {
+        let filler = Hash32::default();
+        let mt: MerkleTree =
+            MerkleTree::from_leaves_with_mode(sequential_leaves(3), filler, HashingMode::Rfc6962)
+                .unwrap();
+
+        let mut expected: MerkleTree =
+            MerkleTree::new_with_mode(2, filler.to_hex(), HashingMode::Rfc6962);
+        for (i, leaf) in sequential_leaves(3).iter().enumerate() {
+            expected.set(expected.leaf_range().start + i, leaf.to_hex());
+        }
+        assert_eq!(mt.root(), expected.root());
+        assert_eq!(
+            MerkleTree::<Sha3_256>::verify_with_mode(
+                mt.proof(2),
+                Hash32([0x22; 32]).to_hex(),
+                mt.mode()
+            ),
+            mt.root()
+        );
+    }
+
+    #[test]
+    fn should_hash_large_levels_like_a_serial_pass() {
+        // Large enough for the levels to be split into parallel chunks when the rayon feature is enabled
+        let leaves: Vec<Hash32> = (0..5000u32)
+            .map(|i| Sha3_256::hash(&i.to_be_bytes()))
+            .collect();
+        for mode in [HashingMode::Plain, HashingMode::Rfc6962] {
+            let mt: MerkleTree =
+                MerkleTree::from_leaves_with_mode(leaves.clone(), Hash32::default(), mode).unwrap();
+
+            let mut expected = vec![Hash32::default(); 2 * 8192];
+            for (i, leaf) in leaves.iter().enumerate() {
+                expected[8192 + i] = mode.leaf_node::<Sha3_256>(leaf);
+            }
+            expected[8192 + 5000..].fill(mode.leaf_node::<Sha3_256>(&Hash32::default()));
+            for i in (1..8192).rev() {
+                expected[i] = mode.parent_node::<Sha3_256>(&expected[2 * i], &expected[2 * i + 1]);
+            }
+            assert_eq!(mt.representation, expected);
+
+            let mut batched = mt.clone();
+            let updates: Vec<(usize, Hash32)> = (0..8192)
+                .step_by(3)
+                .map(|i| (i, leaves[i % 5000]))
+                .collect();
+            batched.set_many(&updates).unwrap();
+            for (leaf_index, hash) in &updates {
+                expected[8192 + leaf_index] = mode.leaf_node::<Sha3_256>(hash);
+            }
+            for i in (1..8192).rev() {
+                expected[i] = mode.parent_node::<Sha3_256>(&expected[2 * i], &expected[2 * i + 1]);
+            }
+            assert_eq!(batched.representation, expected);
+        }
+    }
+
+    #[test]
+    fn should_set_many_leaves_like_setting_them_one_by_one() {
+        for mode in [HashingMode::Plain, HashingMode::Rfc6962] {
+            let mut batched: MerkleTree =
+                MerkleTree::from_leaves_with_mode(sequential_leaves(16), Hash32::default(), mode)
+                    .unwrap();
+            let mut one_by_one = batched.clone();
+
+            let updates: Vec<(usize, Hash32)> = [0, 1, 7, 3, 15, 8, 1]
+                .iter()
+                .enumerate()
+                .map(|(i, leaf_index)| (*leaf_index, Hash32([0xa0 + i as u8; 32])))
+                .collect();
+            batched.set_many(&updates).unwrap();
+            for (leaf_index, hash) in &updates {
+                one_by_one.set(one_by_one.leaf_range().start + leaf_index, hash.to_hex());
+            }
+
+            assert_eq!(batched.root(), one_by_one.root());
+            for index in 1..batched.leaf_range().end {
+                assert_eq!(batched.get(index), one_by_one.get(index));
+            }
+        }
+    }
+
+    #[test]
+    fn should_not_set_any_leaf_when_one_of_many_is_out_of_range() {
+        let mut mt: MerkleTree = MerkleTree::from_leaves(sequential_leaves(4)).unwrap();
+        let root = mt.root();
+
+        assert_eq!(
+            mt.set_many(&[(0, Hash32([0xff; 32])), (4, Hash32([0xff; 32]))]),
+            Err(Error::LeafOutOfRange {
+                index: 4,
+                leaf_count: 4
+            })
+        );
+        assert_eq!(mt.root(), root);
+        assert_eq!(mt.set_many(&[]), Ok(()));
+        assert_eq!(mt.root(), root);
+    }
+
+    #[test]
+    fn should_verify_a_proof_against_a_root_regardless_of_hex_case_and_prefix() {
+        let mt: MerkleTree = MerkleTree::from_leaves(sequential_leaves(16)).unwrap();
+        let path = mt.proof(3);
+        let root = mt.root();
+        let leaf = "0x3333333333333333333333333333333333333333333333333333333333333333";
+
+        assert_eq!(
+            MerkleTree::<Sha3_256>::verify_against(&root, leaf, &path),
+            Ok(true)
+        );
+        assert_eq!(
+            MerkleTree::<Sha3_256>::verify_against(&root.to_uppercase(), leaf, &path),
+            Ok(true)
+        );
+        assert_eq!(
+            MerkleTree::<Sha3_256>::verify_against(
+                &root,
+                "0x5555555555555555555555555555555555555555555555555555555555555555",
+                &path
+            ),
+            Ok(false)
+        );
+        assert!(matches!(
+            MerkleTree::<Sha3_256>::verify_against("0xnot a root", leaf, &path),
+            Err(Error::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn should_report_the_level_where_a_proof_diverges_from_the_intermediates() {
+        let mt: MerkleTree = MerkleTree::from_leaves(sequential_leaves(16)).unwrap();
+        let path = mt.proof(3);
+        let leaf = "0x3333333333333333333333333333333333333333333333333333333333333333";
+        let intermediates = vec![mt.get(9).to_hex(), mt.get(4).to_hex(), mt.get(1).to_hex()];
+
+        let result = MerkleTree::<Sha3_256>::verify_against_intermediates(
+            &mt.root(),
+            leaf,
+            &path,
+            &intermediates,
+            HashingMode::Plain,
+        );
+        assert_eq!(
+            result,
+            Err(Error::PathDiverged {
+                level: 3,
+                expected: *mt.get(1),
+                computed: *mt.get(2)
+            })
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!(
+                "proof path diverged 3 levels above the leaf: expected {}, computed {}",
+                mt.get(1),
+                mt.get(2)
+            )
+        );
+    }
+
     #[test]
     fn should_create_a_merkle_tree_and_do_an_ad_hoc_mutation() {
         let initial_leaf =
             String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
-        let mut mt: MerkleTree = MerkleTree::new(5, initial_leaf);
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf);
 
         for (i, index) in mt.leaf_range().enumerate() {
             let huge_hex_int = BigUint::from_str_radix(
@@ -355,7 +1418,7 @@ mod tests {
     fn should_come_up_with_a_merkle_proof_path() {
         let initial_leaf =
             String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
-        let mut mt: MerkleTree = MerkleTree::new(5, initial_leaf);
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf);
 
         for (i, index) in mt.leaf_range().enumerate() {
             let huge_hex_int = BigUint::from_str_radix(
@@ -401,7 +1464,7 @@ mod tests {
     fn should_verify_a_merkle_proof_given_a_path_and_leaf() {
         let initial_leaf =
             String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
-        let mut mt: MerkleTree = MerkleTree::new(5, initial_leaf);
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf);
 
         for (i, index) in mt.leaf_range().enumerate() {
             let huge_hex_int = BigUint::from_str_radix(
@@ -424,7 +1487,7 @@ mod tests {
         let proof_path = mt.proof(3);
 
         assert_ne!(
-            MerkleTree::verify(
+            MerkleTree::<Sha3_256>::verify(
                 proof_path,
                 "0x5555555555555555555555555555555555555555555555555555555555555555".to_owned()
             ),
@@ -437,7 +1500,7 @@ mod tests {
     fn should_verify_a_merkle_proof_given_a_path_and_leaf_that_belongs_to_the_path() {
         let initial_leaf =
             String::from("0x0000000000000000000000000000000000000000000000000000000000000000");
-        let mut mt: MerkleTree = MerkleTree::new(5, initial_leaf);
+        let mut mt: MerkleTree = MerkleTree::new(4, initial_leaf);
 
         for (i, index) in mt.leaf_range().enumerate() {
             let huge_hex_int = BigUint::from_str_radix(
@@ -460,7 +1523,7 @@ mod tests {
         let proof_path = mt.proof(3);
 
         assert_eq!(
-            MerkleTree::verify(
+            MerkleTree::<Sha3_256>::verify(
                 proof_path,
                 "0x3333333333333333333333333333333333333333333333333333333333333333".to_owned()
             ),
@@ -468,4 +1531,43 @@ mod tests {
             "Retrieved root should be equal to the calculated root since the leaf is a part of the path"
         )
     }
+
+    #[test]
+    fn should_hash_raw_leaf_data_with_the_tree_hasher() {
+        let data = ["alice", "bob", "carol"];
+        let mut mt: MerkleTree = MerkleTree::from_data(data).unwrap();
+        let mut expected: MerkleTree =
+            MerkleTree::from_leaves(data.map(|d| Sha3_256::hash(d.as_bytes()))).unwrap();
+        assert_eq!(mt.root(), expected.root());
+        assert_eq!(mt.leaf_hashing(), LeafHashing::Single);
+
+        mt.set_data(3, b"dave").unwrap();
+        expected.set_many(&[(3, Sha3_256::hash(b"dave"))]).unwrap();
+        assert_eq!(mt.root(), expected.root());
+        assert!(matches!(
+            mt.set_data(4, "eve"),
+            Err(Error::LeafOutOfRange { index: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn should_double_hash_leaf_data_like_the_standard_merkle_tree() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 64]).collect();
+        let mt: MerkleTree =
+            MerkleTree::from_data_with_hashing(&data, HashingMode::SortedPair, LeafHashing::Double)
+                .unwrap();
+        let leaves = data
+            .iter()
+            .map(|d| Sha3_256::hash(Sha3_256::hash(d).as_ref()));
+        let expected: MerkleTree =
+            MerkleTree::from_leaves_with_mode(leaves, Hash32::default(), HashingMode::SortedPair)
+                .unwrap();
+        assert_eq!(mt.root(), expected.root());
+
+        // A proof is verified with the leaf hash of the data, which is kept through a snapshot
+        let restored: MerkleTree = MerkleTree::from_bytes(&mt.to_bytes()).unwrap();
+        assert_eq!(restored.leaf_hashing(), LeafHashing::Double);
+        let leaf = restored.hash_leaf(&data[2]);
+        assert!(mt.merkle_proof(2).unwrap().verify(&leaf, mt.root_hash()));
+    }
 }
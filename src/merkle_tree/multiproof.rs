@@ -0,0 +1,445 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use crate::hasher::{Hash32, Hasher, HashingMode, Sha3_256};
+use crate::merkle_tree::Error;
+
+/// A single proof for many leaves of a merkle tree, holding only the sibling hashes that can not be computed from
+/// the proven leaves themselves
+///
+/// The proof hashes and flags follow the layout of OpenZeppelin's `multiProofVerify`: the leaves and the hashes computed
+/// from them form a queue, and for every flag the next node of the queue is combined either with the next node of the
+/// queue (flag set) or with the next proof hash (flag unset). A tree built with `HashingMode::SortedPair` and Keccak-256
+/// can therefore be checked by those contracts, passing the leaves in the order of `leaf_indices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "", try_from = "MultiProofFields", into = "MultiProofFields")
+)]
+pub struct MultiProof<H: Hasher = Sha3_256> {
+    leaf_indices: Vec<usize>,
+    depth: u32,
+    mode: HashingMode,
+    proof: Vec<Hash32>,
+    proof_flags: Vec<bool>,
+    hasher: PhantomData<H>,
+}
+
+// The fields of a multiproof as they are serialized, deserialized proofs are validated the same way as constructed ones
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MultiProofFields {
+    leaf_indices: Vec<u64>,
+    depth: u32,
+    mode: HashingMode,
+    proof: Vec<Hash32>,
+    proof_flags: Vec<bool>,
+}
+
+#[cfg(feature = "serde")]
+impl<H: Hasher> From<MultiProof<H>> for MultiProofFields {
+    fn from(proof: MultiProof<H>) -> Self {
+        MultiProofFields {
+            leaf_indices: proof.leaf_indices.iter().map(|i| *i as u64).collect(),
+            depth: proof.depth,
+            mode: proof.mode,
+            proof: proof.proof,
+            proof_flags: proof.proof_flags,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<H: Hasher> TryFrom<MultiProofFields> for MultiProof<H> {
+    type Error = Error;
+
+    fn try_from(fields: MultiProofFields) -> Result<Self, Error> {
+        let leaf_indices = fields
+            .leaf_indices
+            .into_iter()
+            .map(usize::try_from)
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| Error::InvalidEncoding(String::from("leaf index does not fit a usize")))?;
+        MultiProof::new(
+            leaf_indices,
+            fields.depth,
+            fields.mode,
+            fields.proof,
+            fields.proof_flags,
+        )
+    }
+}
+
+impl<H: Hasher> MultiProof<H> {
+    /// Creates a multiproof from its parts, the leaf indices have to be in the order the leaves are consumed.
+    /// Fails if there are no leaf indices, if they are not strictly descending, if the depth would overflow the index
+    /// calculus or if an index does not fit a tree of that depth
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_indices` - The indices of the proven leaves among the group of leaves, from the last leaf to the first
+    /// * `depth` - The depth of the tree the proof belongs to
+    /// * `mode` - The hashing mode of the tree the proof belongs to
+    /// * `proof` - The sibling hashes that are not computed from the leaves
+    /// * `proof_flags` - Whether each combination takes its second node from the queue (true) or from the proof (false)
+    ///
+    pub fn new(
+        leaf_indices: Vec<usize>,
+        depth: u32,
+        mode: HashingMode,
+        proof: Vec<Hash32>,
+        proof_flags: Vec<bool>,
+    ) -> Result<Self, Error> {
+        if depth >= usize::BITS {
+            return Err(Error::DepthTooLarge {
+                depth,
+                max: usize::BITS - 1,
+            });
+        }
+        if leaf_indices.is_empty() {
+            return Err(Error::NoLeavesToProve);
+        }
+        for pair in leaf_indices.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(Error::DuplicateLeafIndex(pair[0]));
+            }
+            if pair[0] < pair[1] {
+                return Err(Error::MalformedProof(String::from(
+                    "leaf indices are not in descending order",
+                )));
+            }
+        }
+        // The indices are descending, so only the first one can be the largest
+        if leaf_indices[0] >> depth != 0 {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_indices[0],
+                leaf_count: 1 << depth,
+            });
+        }
+        Ok(MultiProof {
+            leaf_indices,
+            depth,
+            mode,
+            proof,
+            proof_flags,
+            hasher: PhantomData,
+        })
+    }
+
+    /// Returns the indices of the proven leaves in the order their hashes have to be passed to verify, which is descending
+    pub fn leaf_indices(&self) -> &[usize] {
+        &self.leaf_indices
+    }
+
+    /// Returns the depth of the tree the proof belongs to
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the hashing mode of the tree the proof belongs to
+    pub fn mode(&self) -> HashingMode {
+        self.mode
+    }
+
+    /// Returns the sibling hashes that can not be computed from the leaves
+    pub fn proof(&self) -> &[Hash32] {
+        &self.proof
+    }
+
+    /// Returns for every combination whether its second node comes from the queue of leaves and computed hashes (true) or from the proof (false)
+    pub fn proof_flags(&self) -> &[bool] {
+        &self.proof_flags
+    }
+
+    /// Rebuilds the root hash from all of the proven leaves together
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The hashes of the proven leaves, as they were given to the tree, in the order of leaf_indices
+    ///
+    pub fn compute_root(&self, leaves: &[Hash32]) -> Result<Hash32, Error> {
+        let malformed = |reason: &str| Error::MalformedProof(String::from(reason));
+        if leaves.len() != self.leaf_indices.len() {
+            return Err(malformed(
+                "the number of leaves does not match the leaf indices",
+            ));
+        }
+        if leaves.len() + self.proof.len() != self.proof_flags.len() + 1 {
+            return Err(malformed(
+                "the number of proof hashes does not match the flags",
+            ));
+        }
+
+        // Track the index of every node in the queue next to its hash, so that nodes are combined in the right order
+        // and a proof can only ever combine a node with its own sibling
+        let leaf_count = 1usize.checked_shl(self.depth).ok_or(Error::DepthTooLarge {
+            depth: self.depth,
+            max: usize::BITS - 1,
+        })?;
+        let mut queue: VecDeque<(usize, Hash32)> = VecDeque::with_capacity(leaves.len());
+        for (leaf_index, leaf) in self.leaf_indices.iter().zip(leaves) {
+            if *leaf_index >= leaf_count {
+                return Err(Error::LeafOutOfRange {
+                    index: *leaf_index,
+                    leaf_count,
+                });
+            }
+            queue.push_back((leaf_count + leaf_index, self.mode.leaf_node::<H>(leaf)));
+        }
+        let mut proof = self.proof.iter();
+
+        for flag in &self.proof_flags {
+            let (index, hash) = queue
+                .pop_front()
+                .ok_or_else(|| malformed("ran out of nodes to combine"))?;
+            let sibling = if *flag {
+                match queue.pop_front() {
+                    Some((sibling_index, sibling)) if sibling_index == index ^ 1 => sibling,
+                    _ => return Err(malformed("flag does not point at a sibling")),
+                }
+            } else {
+                *proof
+                    .next()
+                    .ok_or_else(|| malformed("ran out of proof hashes"))?
+            };
+            if index <= 1 {
+                return Err(malformed("combines the root with another node"));
+            }
+            let parent = if index.is_multiple_of(2) {
+                self.mode.parent_node::<H>(&hash, &sibling)
+            } else {
+                self.mode.parent_node::<H>(&sibling, &hash)
+            };
+            queue.push_back((index / 2, parent));
+        }
+
+        match (queue.pop_front(), proof.next()) {
+            (Some((1, root)), None) if queue.is_empty() => Ok(root),
+            _ => Err(malformed("does not end in the root")),
+        }
+    }
+
+    /// Returns true if the proof shows that all of the leaves are a part of the tree with the given root
+    ///
+    /// # Arguments
+    ///
+    /// * `leaves` - The hashes of the proven leaves, as they were given to the tree, in the order of leaf_indices
+    /// * `root` - The root hash of the tree
+    ///
+    pub fn verify(&self, leaves: &[Hash32], root: &Hash32) -> bool {
+        self.compute_root(leaves)
+            .is_ok_and(|computed_root| computed_root == *root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleTree;
+
+    fn leaves() -> Vec<Hash32> {
+        (0..16).map(|i| Hash32([i as u8 * 0x11; 32])).collect()
+    }
+
+    fn leaves_of(proof: &MultiProof) -> Vec<Hash32> {
+        proof.leaf_indices().iter().map(|i| leaves()[*i]).collect()
+    }
+
+    // A port of processMultiProof from OpenZeppelin's MerkleProof library, which knows nothing about leaf indices
+    fn open_zeppelin_process_multi_proof(
+        proof: &[Hash32],
+        proof_flags: &[bool],
+        leaves: &[Hash32],
+    ) -> Hash32 {
+        let total_hashes = proof_flags.len();
+        assert_eq!(leaves.len() + proof.len(), total_hashes + 1);
+        let mut hashes = Vec::with_capacity(total_hashes);
+        let (mut leaf_pos, mut hash_pos, mut proof_pos) = (0, 0, 0);
+        for flag in proof_flags {
+            let mut next = || {
+                if leaf_pos < leaves.len() {
+                    leaf_pos += 1;
+                    leaves[leaf_pos - 1]
+                } else {
+                    hash_pos += 1;
+                    hashes[hash_pos - 1]
+                }
+            };
+            let a = next();
+            let b = if *flag {
+                next()
+            } else {
+                proof_pos += 1;
+                proof[proof_pos - 1]
+            };
+            hashes.push(HashingMode::SortedPair.parent_node::<Sha3_256>(&a, &b));
+        }
+        if total_hashes > 0 {
+            assert_eq!(proof_pos, proof.len());
+            hashes[total_hashes - 1]
+        } else if !leaves.is_empty() {
+            leaves[0]
+        } else {
+            proof[0]
+        }
+    }
+
+    #[test]
+    fn should_verify_a_multiproof_for_many_leaves() {
+        for mode in [
+            HashingMode::Plain,
+            HashingMode::Rfc6962,
+            HashingMode::SortedPair,
+        ] {
+            let mt: MerkleTree =
+                MerkleTree::from_leaves_with_mode(leaves(), Hash32::default(), mode).unwrap();
+            let proof = mt.multiproof(&[1, 2, 3, 9, 14]).unwrap();
+
+            assert_eq!(proof.leaf_indices(), &[14, 9, 3, 2, 1]);
+            assert!(proof.verify(&leaves_of(&proof), mt.root_hash()));
+
+            let mut tampered = leaves_of(&proof);
+            tampered[2] = Hash32([0xff; 32]);
+            assert!(!proof.verify(&tampered, mt.root_hash()));
+        }
+    }
+
+    #[test]
+    fn should_only_include_the_siblings_that_can_not_be_computed() {
+        let mt: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+
+        // Leaves 0 to 3 make up a whole subtree, so only the siblings above it are needed
+        let proof = mt.multiproof(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(proof.proof(), &[*mt.get(5), *mt.get(3)]);
+        assert_eq!(proof.proof_flags(), &[true, true, true, false, false]);
+        assert!(proof.verify(&leaves_of(&proof), mt.root_hash()));
+
+        // Proving every leaf needs no proof hashes at all
+        let proof = mt.multiproof(&(0..16).collect::<Vec<usize>>()).unwrap();
+        assert!(proof.proof().is_empty());
+        assert!(proof.proof_flags().iter().all(|flag| *flag));
+        assert!(proof.verify(&leaves_of(&proof), mt.root_hash()));
+
+        // A single leaf needs the same hashes as a regular proof
+        let proof = mt.multiproof(&[5]).unwrap();
+        assert_eq!(proof.proof(), mt.merkle_proof(5).unwrap().siblings());
+    }
+
+    #[test]
+    fn should_be_verifiable_by_the_open_zeppelin_algorithm() {
+        let mt: MerkleTree =
+            MerkleTree::from_leaves_with_mode(leaves(), Hash32::default(), HashingMode::SortedPair)
+                .unwrap();
+        for indices in [
+            vec![0],
+            vec![4, 5, 6, 15],
+            vec![0, 3, 7, 8, 12],
+            vec![10, 11],
+        ] {
+            let proof = mt.multiproof(&indices).unwrap();
+            assert_eq!(
+                open_zeppelin_process_multi_proof(
+                    proof.proof(),
+                    proof.proof_flags(),
+                    &leaves_of(&proof)
+                ),
+                *mt.root_hash()
+            );
+        }
+    }
+
+    #[test]
+    fn should_prove_the_only_leaf_of_a_tree_of_zero_depth() {
+        let mt: MerkleTree = MerkleTree::from_leaves(vec![Hash32([7; 32])]).unwrap();
+        let proof = mt.multiproof(&[0]).unwrap();
+
+        assert!(proof.proof().is_empty());
+        assert!(proof.verify(&[Hash32([7; 32])], mt.root_hash()));
+    }
+
+    #[test]
+    fn should_refuse_to_prove_duplicate_or_out_of_range_leaves() {
+        let mt: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+
+        assert_eq!(mt.multiproof(&[3, 1, 3]), Err(Error::DuplicateLeafIndex(3)));
+        assert_eq!(
+            mt.multiproof(&[16]),
+            Err(Error::LeafOutOfRange {
+                index: 16,
+                leaf_count: 16
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_malformed_multiproofs() {
+        let mt: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+        let proof = mt.multiproof(&[2, 3, 9]).unwrap();
+        let leaves = leaves_of(&proof);
+
+        assert!(matches!(
+            proof.compute_root(&leaves[1..]),
+            Err(Error::MalformedProof(_))
+        ));
+
+        // Claiming that leaf 9 is combined with leaf 3 instead of its own sibling must not verify
+        let mut flags = proof.proof_flags().to_vec();
+        flags[0] = true;
+        let mut hashes = proof.proof().to_vec();
+        hashes.remove(0);
+        hashes.push(*mt.root_hash());
+        let forged = MultiProof::<Sha3_256>::new(
+            proof.leaf_indices().to_vec(),
+            proof.depth(),
+            proof.mode(),
+            hashes,
+            flags,
+        )
+        .unwrap();
+        assert!(!forged.verify(&leaves, mt.root_hash()));
+    }
+
+    #[test]
+    fn should_refuse_to_create_a_multiproof_that_can_not_be_computed() {
+        let new = |leaf_indices: Vec<usize>, depth: u32| {
+            MultiProof::<Sha3_256>::new(leaf_indices, depth, HashingMode::Plain, vec![], vec![])
+        };
+
+        assert_eq!(
+            new(vec![0], 64),
+            Err(Error::DepthTooLarge {
+                depth: 64,
+                max: usize::BITS - 1
+            })
+        );
+        assert_eq!(
+            new(vec![16, 3], 4),
+            Err(Error::LeafOutOfRange {
+                index: 16,
+                leaf_count: 16
+            })
+        );
+        assert_eq!(new(vec![], 4), Err(Error::NoLeavesToProve));
+        assert_eq!(new(vec![5, 5], 4), Err(Error::DuplicateLeafIndex(5)));
+        assert!(matches!(new(vec![2, 7], 4), Err(Error::MalformedProof(_))));
+
+        let mt: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+        assert_eq!(mt.multiproof(&[]), Err(Error::NoLeavesToProve));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_serialize_a_multiproof_and_validate_it_on_the_way_back() {
+        let mt: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+        let proof = mt.multiproof(&[2, 3, 9]).unwrap();
+        let mut json = serde_json::to_value(&proof).unwrap();
+
+        assert_eq!(json["leaf_indices"], serde_json::json!([9, 3, 2]));
+        let decoded: MultiProof = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(decoded, proof);
+
+        json["depth"] = 64.into();
+        assert!(serde_json::from_value::<MultiProof>(json).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+use std::marker::PhantomData;
+
+use crate::hasher::{Hash32, Hasher, HashingMode, Sha3_256};
+use crate::merkle_tree::{Error, Handedness, HASH_LENGTH};
+
+// The binary encoding is the mode byte, the depth byte and the leaf index as a big endian u64 followed by the siblings
+const ENCODING_HEADER_LENGTH: usize = 10;
+
+/// An inclusion proof for a single leaf of a merkle tree
+///
+/// The proof holds the index of the leaf among the leaves and the sibling hashes from the leaf level up to the level
+/// below the root. Whether the current node is a left or a right child at each level follows from the bits of the leaf index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "", try_from = "ProofFields", into = "ProofFields")
+)]
+pub struct MerkleProof<H: Hasher = Sha3_256> {
+    leaf_index: usize,
+    depth: u32,
+    mode: HashingMode,
+    siblings: Vec<Hash32>,
+    hasher: PhantomData<H>,
+}
+
+// The fields of a proof as they are serialized, deserialized proofs are validated the same way as constructed ones
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ProofFields {
+    leaf_index: u64,
+    depth: u32,
+    mode: HashingMode,
+    siblings: Vec<Hash32>,
+}
+
+#[cfg(feature = "serde")]
+impl<H: Hasher> From<MerkleProof<H>> for ProofFields {
+    fn from(proof: MerkleProof<H>) -> Self {
+        ProofFields {
+            leaf_index: proof.leaf_index as u64,
+            depth: proof.depth,
+            mode: proof.mode,
+            siblings: proof.siblings,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<H: Hasher> TryFrom<ProofFields> for MerkleProof<H> {
+    type Error = Error;
+
+    fn try_from(fields: ProofFields) -> Result<Self, Error> {
+        if fields.siblings.len() != fields.depth as usize {
+            return Err(Error::InvalidEncoding(format!(
+                "proof of depth {} has {} siblings",
+                fields.depth,
+                fields.siblings.len()
+            )));
+        }
+        let leaf_index = usize::try_from(fields.leaf_index)
+            .map_err(|_| Error::InvalidEncoding(String::from("leaf index does not fit a usize")))?;
+        MerkleProof::new(leaf_index, fields.siblings, fields.mode)
+    }
+}
+
+impl<H: Hasher> MerkleProof<H> {
+    /// Creates a proof for the leaf at leaf_index, the depth of the tree is the number of siblings
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - The index of the leaf among the group of leaves
+    /// * `siblings` - The hashes of the siblings along the path, starting at the leaf level
+    /// * `mode` - The hashing mode of the tree the proof belongs to
+    ///
+    pub fn new(leaf_index: usize, siblings: Vec<Hash32>, mode: HashingMode) -> Result<Self, Error> {
+        let depth = siblings.len() as u32;
+        if depth >= usize::BITS {
+            return Err(Error::DepthTooLarge {
+                depth,
+                max: usize::BITS - 1,
+            });
+        }
+        if leaf_index >> depth != 0 {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: 1 << depth,
+            });
+        }
+        Ok(MerkleProof {
+            leaf_index,
+            depth,
+            mode,
+            siblings,
+            hasher: PhantomData,
+        })
+    }
+
+    /// Creates a proof from a path in the format of MerkleTree::proof, recovering the leaf index from the handedness of each level
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The handedness and hexadecimal sibling hash of each level, starting at the leaf level
+    /// * `mode` - The hashing mode of the tree the proof belongs to
+    ///
+    pub fn from_path(path: &[(Handedness, String)], mode: HashingMode) -> Result<Self, Error> {
+        if path.len() >= usize::BITS as usize {
+            return Err(Error::DepthTooLarge {
+                depth: path.len() as u32,
+                max: usize::BITS - 1,
+            });
+        }
+        let mut leaf_index = 0;
+        let mut siblings = Vec::with_capacity(path.len());
+        for (level, (handedness, sibling)) in path.iter().enumerate() {
+            if *handedness == Handedness::Right {
+                leaf_index |= 1 << level;
+            }
+            siblings.push(Hash32::from_hex(sibling)?);
+        }
+        Self::new(leaf_index, siblings, mode)
+    }
+
+    /// Returns the index of the proven leaf among the group of leaves
+    pub fn leaf_index(&self) -> usize {
+        self.leaf_index
+    }
+
+    /// Returns the depth of the tree the proof belongs to
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the hashing mode of the tree the proof belongs to
+    pub fn mode(&self) -> HashingMode {
+        self.mode
+    }
+
+    /// Returns the sibling hashes along the path, starting at the leaf level
+    pub fn siblings(&self) -> &[Hash32] {
+        &self.siblings
+    }
+
+    /// Returns whether the node on the path at the given level is a left or a right child, level 0 being the leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The number of levels above the leaf
+    ///
+    pub fn handedness(&self, level: u32) -> Handedness {
+        // The index of the node among the nodes at its level is the leaf index shifted by the levels climbed
+        if (self.leaf_index >> level).is_multiple_of(2) {
+            Handedness::Left
+        } else {
+            Handedness::Right
+        }
+    }
+
+    /// Returns the proof in the path format of MerkleTree::proof
+    pub fn path(&self) -> Vec<(Handedness, String)> {
+        (0..self.depth)
+            .zip(self.siblings.iter())
+            .map(|(level, sibling)| (self.handedness(level), sibling.to_hex()))
+            .collect()
+    }
+
+    /// Returns the root hash calculated from the leaf and the sibling hashes of the proof
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash of the leaf, as it was given to the tree
+    ///
+    pub fn compute_root(&self, leaf: &Hash32) -> Hash32 {
+        self.intermediate_nodes(leaf)
+            .last()
+            .copied()
+            .unwrap_or_else(|| self.mode.leaf_node::<H>(leaf))
+    }
+
+    // Returns the nodes on the path from the leaf node up to and including the root, skipping the leaf node itself
+    pub(crate) fn intermediate_nodes(&self, leaf: &Hash32) -> Vec<Hash32> {
+        let mut current = self.mode.leaf_node::<H>(leaf);
+        let mut nodes = Vec::with_capacity(self.siblings.len());
+        for (level, sibling) in (0..self.depth).zip(self.siblings.iter()) {
+            current = match self.handedness(level) {
+                Handedness::Left => self.mode.parent_node::<H>(&current, sibling),
+                Handedness::Right => self.mode.parent_node::<H>(sibling, &current),
+            };
+            nodes.push(current);
+        }
+        nodes
+    }
+
+    /// Returns true if the proof shows that the leaf is a part of the tree with the given root
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash of the leaf, as it was given to the tree
+    /// * `root` - The root hash of the tree
+    ///
+    pub fn verify(&self, leaf: &Hash32, root: &Hash32) -> bool {
+        self.compute_root(leaf) == *root
+    }
+
+    /// Verifies the proof while checking the nodes on the path against the expected intermediate hashes, returning an
+    /// error naming the first level where the computed path diverges from them
+    ///
+    /// Returns Ok(false) if all of the intermediate hashes match but the computed root does not
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf` - The hash of the leaf, as it was given to the tree
+    /// * `root` - The root hash of the tree
+    /// * `intermediates` - The expected nodes on the path, starting at the parent of the leaf (level 1)
+    ///
+    pub fn verify_with_intermediates(
+        &self,
+        leaf: &Hash32,
+        root: &Hash32,
+        intermediates: &[Hash32],
+    ) -> Result<bool, Error> {
+        let computed_nodes = self.intermediate_nodes(leaf);
+        for (level, (expected, computed)) in
+            intermediates.iter().zip(computed_nodes.iter()).enumerate()
+        {
+            if expected != computed {
+                return Err(Error::PathDiverged {
+                    level: level + 1,
+                    expected: *expected,
+                    computed: *computed,
+                });
+            }
+        }
+        Ok(self.compute_root(leaf) == *root)
+    }
+
+    /// Encodes the proof compactly as bytes: the hashing mode, the depth, the leaf index as a big endian u64 and the siblings
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(ENCODING_HEADER_LENGTH + HASH_LENGTH * self.siblings.len());
+        bytes.push(self.mode.id());
+        bytes.push(self.depth as u8);
+        bytes.extend_from_slice(&(self.leaf_index as u64).to_be_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling.as_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a proof from the encoding produced by to_bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded proof
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < ENCODING_HEADER_LENGTH {
+            return Err(Error::InvalidEncoding(String::from("proof is too short")));
+        }
+        let mode = HashingMode::from_id(bytes[0])
+            .ok_or_else(|| Error::InvalidEncoding(format!("unknown hashing mode {}", bytes[0])))?;
+        let depth = bytes[1] as usize;
+        let leaf_index = u64::from_be_bytes(bytes[2..ENCODING_HEADER_LENGTH].try_into().unwrap());
+        let leaf_index = usize::try_from(leaf_index)
+            .map_err(|_| Error::InvalidEncoding(String::from("leaf index does not fit a usize")))?;
+
+        let encoded_siblings = &bytes[ENCODING_HEADER_LENGTH..];
+        if encoded_siblings.len() != depth * HASH_LENGTH {
+            return Err(Error::InvalidEncoding(format!(
+                "proof of depth {} should have {} bytes of siblings, got {}",
+                depth,
+                depth * HASH_LENGTH,
+                encoded_siblings.len()
+            )));
+        }
+        let siblings = encoded_siblings
+            .chunks_exact(HASH_LENGTH)
+            .map(Hash32::from_slice)
+            .collect::<Result<Vec<Hash32>, Error>>()?;
+        Self::new(leaf_index, siblings, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleTree;
+
+    fn create_merkle_tree(mode: HashingMode) -> MerkleTree {
+        let leaves = (0..16).map(|i| Hash32([i as u8 * 0x11; 32]));
+        MerkleTree::from_leaves_with_mode(leaves, Hash32::default(), mode).unwrap()
+    }
+
+    #[test]
+    fn should_verify_a_merkle_proof_against_the_root() {
+        for mode in [HashingMode::Plain, HashingMode::Rfc6962] {
+            let mt = create_merkle_tree(mode);
+            let proof = mt.merkle_proof(3).unwrap();
+
+            assert_eq!(proof.leaf_index(), 3);
+            assert_eq!(proof.depth(), 4);
+            assert_eq!(proof.mode(), mode);
+            assert!(proof.verify(&Hash32([0x33; 32]), mt.root_hash()));
+            assert!(!proof.verify(&Hash32([0x55; 32]), mt.root_hash()));
+        }
+    }
+
+    #[test]
+    fn should_derive_handedness_from_the_leaf_index() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let proof = mt.merkle_proof(3).unwrap();
+
+        assert_eq!(proof.path(), mt.proof(3));
+        assert_eq!(
+            (0..4)
+                .map(|level| proof.handedness(level))
+                .collect::<Vec<Handedness>>(),
+            vec![
+                Handedness::Right,
+                Handedness::Right,
+                Handedness::Left,
+                Handedness::Left
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_verify_a_proof_for_another_leaf_index() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let proof = mt.merkle_proof(3).unwrap();
+        let moved =
+            MerkleProof::<Sha3_256>::new(2, proof.siblings().to_vec(), proof.mode()).unwrap();
+
+        assert!(!moved.verify(&Hash32([0x33; 32]), mt.root_hash()));
+    }
+
+    #[test]
+    fn should_refuse_a_leaf_index_that_does_not_fit_the_depth() {
+        let result =
+            MerkleProof::<Sha3_256>::new(4, vec![Hash32::default(); 2], HashingMode::Plain);
+        assert_eq!(
+            result,
+            Err(Error::LeafOutOfRange {
+                index: 4,
+                leaf_count: 4
+            })
+        );
+    }
+
+    #[test]
+    fn should_create_a_merkle_proof_from_a_path() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let proof = MerkleProof::<Sha3_256>::from_path(&mt.proof(11), HashingMode::Plain).unwrap();
+
+        assert_eq!(proof, mt.merkle_proof(11).unwrap());
+    }
+
+    #[test]
+    fn should_name_the_level_where_a_proof_diverges_from_the_intermediates() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let proof = mt.merkle_proof(3).unwrap();
+        let leaf = Hash32([0x33; 32]);
+        // The nodes on the path of leaf 3 are at indices 9, 4 and 2 of the tree
+        let mut intermediates = vec![*mt.get(9), *mt.get(4), *mt.get(2)];
+
+        assert_eq!(
+            proof.verify_with_intermediates(&leaf, mt.root_hash(), &intermediates),
+            Ok(true)
+        );
+        assert_eq!(
+            proof.verify_with_intermediates(&leaf, &Hash32::default(), &intermediates),
+            Ok(false)
+        );
+
+        intermediates[1] = Hash32::default();
+        assert_eq!(
+            proof.verify_with_intermediates(&leaf, mt.root_hash(), &intermediates),
+            Err(Error::PathDiverged {
+                level: 2,
+                expected: Hash32::default(),
+                computed: *mt.get(4)
+            })
+        );
+    }
+
+    #[test]
+    fn should_encode_and_decode_a_merkle_proof_as_bytes() {
+        let mt = create_merkle_tree(HashingMode::Rfc6962);
+        let proof = mt.merkle_proof(9).unwrap();
+        let bytes = proof.to_bytes();
+
+        assert_eq!(bytes.len(), 10 + 4 * 32);
+        assert_eq!(&bytes[..10], &[1, 4, 0, 0, 0, 0, 0, 0, 0, 9]);
+        assert_eq!(MerkleProof::from_bytes(&bytes), Ok(proof));
+    }
+
+    #[test]
+    fn should_refuse_to_decode_malformed_proofs() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let bytes = mt.merkle_proof(9).unwrap().to_bytes();
+
+        assert!(matches!(
+            MerkleProof::<Sha3_256>::from_bytes(&bytes[..5]),
+            Err(Error::InvalidEncoding(_))
+        ));
+        assert!(matches!(
+            MerkleProof::<Sha3_256>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidEncoding(_))
+        ));
+        let mut unknown_mode = bytes.clone();
+        unknown_mode[0] = 7;
+        assert!(matches!(
+            MerkleProof::<Sha3_256>::from_bytes(&unknown_mode),
+            Err(Error::InvalidEncoding(_))
+        ));
+        let mut index_out_of_range = bytes;
+        index_out_of_range[9] = 16;
+        assert!(matches!(
+            MerkleProof::<Sha3_256>::from_bytes(&index_out_of_range),
+            Err(Error::LeafOutOfRange { .. })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_serialize_a_merkle_proof_to_json_and_back() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let proof = mt.merkle_proof(3).unwrap();
+        let json = serde_json::to_value(&proof).unwrap();
+
+        assert_eq!(json["leaf_index"], 3);
+        assert_eq!(json["depth"], 4);
+        assert_eq!(json["mode"], "Plain");
+        assert_eq!(
+            json["siblings"][0],
+            "0x2222222222222222222222222222222222222222222222222222222222222222"
+        );
+        let decoded: MerkleProof = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, proof);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_serialize_a_merkle_proof_to_cbor_and_back() {
+        let mt = create_merkle_tree(HashingMode::Rfc6962);
+        let proof = mt.merkle_proof(12).unwrap();
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&proof, &mut cbor).unwrap();
+
+        let decoded: MerkleProof = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(decoded, proof);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_refuse_to_deserialize_a_proof_with_a_mismatching_depth() {
+        let mt = create_merkle_tree(HashingMode::Plain);
+        let mut json = serde_json::to_value(mt.merkle_proof(3).unwrap()).unwrap();
+        json["depth"] = 5.into();
+
+        assert!(serde_json::from_value::<MerkleProof>(json).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::marker::PhantomData;
+
+use crate::binary_tree::BinaryTreeBehavior;
+use crate::hasher::{Hash32, Hasher, HashingMode, LeafHashing};
+use crate::merkle_tree::{Error, MerkleTree, Storage, HASH_LENGTH, MAX_DEPTH};
+
+// A snapshot is the node array in the one based layout, with the unused slot 0 holding a header of the magic bytes,
+// the format version, the id of the hash function, the node size, the hashing mode, the depth and the leaf hashing. Files of trees
+// with the mmap feature have the same layout, so a snapshot written to disk can be opened as a tree file
+const MAGIC: &[u8; 8] = b"forrest\0";
+const VERSION: u8 = 1;
+
+// Describes a tree of the given mode, depth and leaf hashing hashed with H in the header that takes the place of slot 0
+pub(crate) fn encode_header<H: Hasher>(
+    mode: HashingMode,
+    depth: u32,
+    leaf_hashing: LeafHashing,
+) -> Hash32 {
+    let mut header = Hash32::default();
+    header.0[..MAGIC.len()].copy_from_slice(MAGIC);
+    header.0[8] = VERSION;
+    header.0[9] = H::ALGORITHM_ID;
+    header.0[10] = HASH_LENGTH as u8;
+    header.0[11] = mode.id();
+    header.0[12] = depth as u8;
+    header.0[13] = leaf_hashing.id();
+    header
+}
+
+// Reads the mode and leaf hashing from a header, checking that it describes a tree hashed with H of the given number
+// of slots
+pub(crate) fn decode_header<H: Hasher>(
+    header: &Hash32,
+    slots: usize,
+) -> Result<(HashingMode, LeafHashing), Error> {
+    let invalid = |reason: &str| Error::InvalidEncoding(format!("snapshot {}", reason));
+    if &header.0[..MAGIC.len()] != MAGIC {
+        return Err(invalid("does not start with the forrest magic bytes"));
+    }
+    if header.0[8] != VERSION {
+        return Err(invalid("has an unsupported format version"));
+    }
+    if header.0[9] != H::ALGORITHM_ID {
+        return Err(invalid("was hashed with another hash function"));
+    }
+    if header.0[10] as usize != HASH_LENGTH {
+        return Err(invalid("has nodes of another size"));
+    }
+    let mode =
+        HashingMode::from_id(header.0[11]).ok_or_else(|| invalid("has an unknown hashing mode"))?;
+    let depth = header.0[12] as u32;
+    if depth > MAX_DEPTH || slots != 2 << depth {
+        return Err(invalid("does not hold the number of nodes of its depth"));
+    }
+    let leaf_hashing =
+        LeafHashing::from_id(header.0[13]).ok_or_else(|| invalid("has an unknown leaf hashing"))?;
+    Ok((mode, leaf_hashing))
+}
+
+impl<H: Hasher, S: Storage> MerkleTree<H, S> {
+    /// Encodes the whole tree as a versioned snapshot: a 32 byte header followed by every node from the root down,
+    /// level by level from left to right
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.representation.len() * HASH_LENGTH);
+        bytes.extend_from_slice(&encode_header::<H>(self.mode, self.depth(), self.leaf_hashing).0);
+        for node in &self.representation[1..] {
+            bytes.extend_from_slice(&node.0);
+        }
+        bytes
+    }
+
+    /// Rehashes every intermediate node from its children, returning an error with the first node that does not match
+    pub fn check_integrity(&self) -> Result<(), Error> {
+        for index in (1..self.leaf_count()).rev() {
+            let hashed = self.mode.parent_node::<H>(
+                &self.representation[Self::get_left_child(index)],
+                &self.representation[Self::get_right_child(index)],
+            );
+            if hashed != self.representation[index] {
+                return Err(Error::CorruptNode(index));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<H: Hasher> MerkleTree<H> {
+    /// Decodes a snapshot created by to_bytes, checking that every node matches the hash of its children
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The snapshot of a tree hashed with the same hash function
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if !bytes.len().is_multiple_of(HASH_LENGTH) || bytes.len() < 2 * HASH_LENGTH {
+            return Err(Error::InvalidEncoding(String::from(
+                "snapshot is not made up of whole nodes",
+            )));
+        }
+        let representation: Vec<Hash32> = bytes
+            .chunks_exact(HASH_LENGTH)
+            .map(|chunk| Hash32::from_slice(chunk).expect("Chunks have the length of a hash"))
+            .collect();
+        let (mode, leaf_hashing) = decode_header::<H>(&representation[0], representation.len())?;
+
+        let mut mt = MerkleTree {
+            representation,
+            mode,
+            leaf_hashing,
+            hasher: PhantomData,
+        };
+        mt.representation[0] = Hash32::default();
+        mt.check_integrity()?;
+        Ok(mt)
+    }
+}
+
+// Trees are serialized as their snapshot, a hex string in human readable formats and plain bytes otherwise
+#[cfg(feature = "serde")]
+impl<H: Hasher, S: Storage> serde::Serialize for MerkleTree<H, S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(self.to_bytes()))
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H: Hasher> serde::Deserialize<'de> for MerkleTree<H> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SnapshotVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SnapshotVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a merkle tree snapshot as a hex string or bytes")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+                hex::decode(v).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Vec<u8>, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        let bytes = if deserializer.is_human_readable() {
+            deserializer.deserialize_str(SnapshotVisitor)?
+        } else {
+            deserializer.deserialize_bytes(SnapshotVisitor)?
+        };
+        MerkleTree::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Sha3_256;
+
+    fn tree() -> MerkleTree {
+        let leaves = (0..5).map(|i| Hash32([i as u8 * 0x11; 32]));
+        MerkleTree::from_leaves_with_mode(leaves, Hash32::default(), HashingMode::Rfc6962).unwrap()
+    }
+
+    #[test]
+    fn should_restore_a_tree_from_its_snapshot() {
+        let mt = tree();
+        let bytes = mt.to_bytes();
+        assert_eq!(bytes.len(), 16 * HASH_LENGTH);
+        assert_eq!(&bytes[..8], b"forrest\0");
+        assert_eq!(&bytes[8..13], &[1, Sha3_256::ALGORITHM_ID, 32, 1, 3]);
+
+        let restored: MerkleTree = MerkleTree::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.root(), mt.root());
+        assert_eq!(restored.mode(), HashingMode::Rfc6962);
+        assert_eq!(restored.merkle_proof(4), mt.merkle_proof(4));
+    }
+
+    #[test]
+    fn should_detect_a_corrupt_node_in_a_snapshot() {
+        let mut bytes = tree().to_bytes();
+        // Flip a bit of the leaf at index 12, which no longer matches its parent at index 6
+        bytes[12 * HASH_LENGTH] ^= 1;
+        assert_eq!(
+            MerkleTree::<Sha3_256>::from_bytes(&bytes).unwrap_err(),
+            Error::CorruptNode(6)
+        );
+    }
+
+    #[test]
+    fn should_refuse_snapshots_it_can_not_read() {
+        let bytes = tree().to_bytes();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 2;
+        let mut wrong_hasher = bytes.clone();
+        wrong_hasher[9] = 0xff;
+        for invalid in [
+            &wrong_version[..],
+            &wrong_hasher[..],
+            &bytes[..bytes.len() - HASH_LENGTH],
+            &bytes[..bytes.len() - 1],
+            &[],
+        ] {
+            assert!(matches!(
+                MerkleTree::<Sha3_256>::from_bytes(invalid),
+                Err(Error::InvalidEncoding(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_round_trip_a_tree_through_json_and_cbor() {
+        let mt = tree();
+
+        let json = serde_json::to_string(&mt).unwrap();
+        assert_eq!(json, format!("\"{}\"", hex::encode(mt.to_bytes())));
+        let from_json: MerkleTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json.root(), mt.root());
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&mt, &mut cbor).unwrap();
+        let from_cbor: MerkleTree = ciborium::from_reader(&cbor[..]).unwrap();
+        assert_eq!(from_cbor.root(), mt.root());
+    }
+}
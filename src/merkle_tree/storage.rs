@@ -0,0 +1,260 @@
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use crate::hasher::Hash32;
+#[cfg(feature = "mmap")]
+use crate::hasher::{Hasher, HashingMode, LeafHashing};
+#[cfg(feature = "mmap")]
+use crate::merkle_tree::snapshot::{decode_header, encode_header};
+#[cfg(feature = "mmap")]
+use crate::merkle_tree::{Error, MerkleTree, HASH_LENGTH};
+#[cfg(feature = "mmap")]
+use memmap2::MmapMut;
+#[cfg(feature = "mmap")]
+use std::fs::OpenOptions;
+#[cfg(feature = "mmap")]
+use std::marker::PhantomData;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+/// Where the nodes of a merkle tree are kept, a flat buffer of hashes in the one based array layout whose slot 0
+/// is never used by the tree itself
+pub trait Storage: Deref<Target = [Hash32]> + DerefMut {
+    /// Writes any changes of the nodes through to the underlying medium
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Nodes kept in memory are always up to date
+impl Storage for Vec<Hash32> {}
+
+/// Nodes kept in a memory mapped file of 32 byte hashes, in the same one based array layout as in memory
+///
+/// The file must not be changed by anything else while it is mapped.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct FileStorage {
+    map: MmapMut,
+}
+
+#[cfg(feature = "mmap")]
+impl FileStorage {
+    // Creates the file, or truncates an existing one, with room for the given number of node slots and maps it
+    fn create<P: AsRef<Path>>(path: P, slots: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((slots * HASH_LENGTH) as u64)?;
+        // SAFETY: the file is only changed through this map for as long as it lives, as documented on the type
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(FileStorage { map })
+    }
+
+    // Maps an existing file
+    fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: the file is only changed through this map for as long as it lives, as documented on the type
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(FileStorage { map })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Deref for FileStorage {
+    type Target = [Hash32];
+
+    fn deref(&self) -> &[Hash32] {
+        // SAFETY: Hash32 is a transparent wrapper around 32 bytes, so it has no alignment requirement and any
+        // bytes are a valid hash. Trailing bytes that do not fill a whole hash are left out
+        unsafe {
+            std::slice::from_raw_parts(
+                self.map.as_ptr() as *const Hash32,
+                self.map.len() / HASH_LENGTH,
+            )
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl DerefMut for FileStorage {
+    fn deref_mut(&mut self) -> &mut [Hash32] {
+        // SAFETY: see deref, the map is borrowed mutably for as long as the slice lives
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.map.as_mut_ptr() as *mut Hash32,
+                self.map.len() / HASH_LENGTH,
+            )
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Storage for FileStorage {
+    fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<H: Hasher> MerkleTree<H, FileStorage> {
+    /// Creates a merkle tree from a list of leaves in a file, overwriting the file if it exists. The depth is picked to
+    /// fit all of the leaves and the remaining leaf slots are padded with the filler leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file holding the nodes
+    /// * `leaves` - The hashes of the leaves from left to right
+    /// * `filler` - The hash given to the leaf slots that are left over when the number of leaves is not a power of two
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn create_file<P: AsRef<Path>, I: IntoIterator<Item = Hash32>>(
+        path: P,
+        leaves: I,
+        filler: Hash32,
+        mode: HashingMode,
+    ) -> Result<Self, Error> {
+        let leaves: Vec<Hash32> = leaves.into_iter().collect();
+        let depth = MerkleTree::<H>::depth_to_fit(leaves.len())?;
+        let mut storage =
+            FileStorage::create(path, 2 * MerkleTree::<H>::leaf_count_for_depth(depth))?;
+        MerkleTree::<H>::build_nodes(&mut storage, &leaves, filler, mode);
+
+        // The tree never uses slot 0, so it holds the same header as a snapshot instead
+        storage[0] = encode_header::<H>(mode, depth, LeafHashing::Single);
+        storage.flush()?;
+
+        Ok(MerkleTree {
+            representation: storage,
+            mode,
+            leaf_hashing: LeafHashing::Single,
+            hasher: PhantomData,
+        })
+    }
+
+    /// Opens a merkle tree that was created in a file or written as a snapshot, as it was last flushed, without
+    /// rehashing any of its nodes. Use check_integrity to verify them
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file holding the nodes
+    ///
+    pub fn open_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let storage = FileStorage::open(path)?;
+        if !storage.map.len().is_multiple_of(HASH_LENGTH) || storage.len() < 2 {
+            return Err(Error::InvalidEncoding(String::from(
+                "tree file is not made up of whole nodes",
+            )));
+        }
+        let (mode, leaf_hashing) = decode_header::<H>(&storage[0], storage.len())?;
+
+        Ok(MerkleTree {
+            representation: storage,
+            mode,
+            leaf_hashing,
+            hasher: PhantomData,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::hasher::Sha3_256;
+    use std::path::PathBuf;
+
+    // A path in the temporary directory that is removed again when the test is done with it
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!(
+                "forrest-{}-{}.tree",
+                std::process::id(),
+                name
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn leaves() -> Vec<Hash32> {
+        (0..11).map(|i| Hash32([i as u8 * 0x11; 32])).collect()
+    }
+
+    #[test]
+    fn should_create_the_same_tree_in_a_file_as_in_memory() {
+        let path = TempPath::new("create");
+        let in_memory: MerkleTree =
+            MerkleTree::from_leaves_with_mode(leaves(), Hash32::default(), HashingMode::Rfc6962)
+                .unwrap();
+        let in_file: MerkleTree<Sha3_256, FileStorage> =
+            MerkleTree::create_file(&path.0, leaves(), Hash32::default(), HashingMode::Rfc6962)
+                .unwrap();
+
+        assert_eq!(in_file.root(), in_memory.root());
+        assert_eq!(in_file.storage()[1..], in_memory.storage()[1..]);
+        assert_eq!(
+            std::fs::metadata(&path.0).unwrap().len(),
+            (in_memory.node_count() as u64 + 1) * HASH_LENGTH as u64
+        );
+    }
+
+    #[test]
+    fn should_reopen_a_tree_file_without_rehashing() {
+        let path = TempPath::new("reopen");
+        let mut in_memory: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+        {
+            let mut in_file: MerkleTree<Sha3_256, FileStorage> =
+                MerkleTree::create_file(&path.0, leaves(), Hash32::default(), HashingMode::Plain)
+                    .unwrap();
+            in_file.set_many(&[(3, Hash32([0xff; 32]))]).unwrap();
+            in_file.flush().unwrap();
+        }
+        in_memory.set_many(&[(3, Hash32([0xff; 32]))]).unwrap();
+
+        let reopened: MerkleTree<Sha3_256, FileStorage> = MerkleTree::open_file(&path.0).unwrap();
+        assert_eq!(reopened.depth(), 4);
+        assert_eq!(reopened.mode(), HashingMode::Plain);
+        assert_eq!(reopened.root(), in_memory.root());
+        assert_eq!(reopened.merkle_proof(3), in_memory.merkle_proof(3));
+    }
+
+    #[test]
+    fn should_open_a_snapshot_written_to_disk_as_a_tree_file() {
+        let path = TempPath::new("snapshot");
+        let in_memory: MerkleTree = MerkleTree::from_leaves(leaves()).unwrap();
+        std::fs::write(&path.0, in_memory.to_bytes()).unwrap();
+
+        let opened: MerkleTree<Sha3_256, FileStorage> = MerkleTree::open_file(&path.0).unwrap();
+        assert_eq!(opened.check_integrity(), Ok(()));
+        assert_eq!(opened.root(), in_memory.root());
+        assert_eq!(opened.to_bytes(), in_memory.to_bytes());
+    }
+
+    #[test]
+    fn should_refuse_to_open_a_file_that_is_not_a_tree() {
+        let path = TempPath::new("invalid");
+        std::fs::write(&path.0, [0u8; 4 * HASH_LENGTH]).unwrap();
+        assert!(matches!(
+            MerkleTree::<Sha3_256, FileStorage>::open_file(&path.0),
+            Err(Error::InvalidEncoding(_))
+        ));
+
+        let missing = TempPath::new("missing");
+        assert!(matches!(
+            MerkleTree::<Sha3_256, FileStorage>::open_file(&missing.0),
+            Err(Error::Io {
+                kind: io::ErrorKind::NotFound,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::binary_tree::{BinaryTree, BinaryTreeBehavior};
+use crate::heap::{Kind, Sift};
+
+// The queue is a binary heap of (handle, value) pairs in the array form of BinaryTree. Next to it, a map from every
+// handle to the index of its node is kept up to date on every swap, so an element can be found in O(1) and then
+// moved up or down into place in O(log n) after its value changed or it was removed. The moves are the ones of Heap,
+// shared through Sift, with a swap that updates the map as well.
+/// A priority queue that hands out a stable handle for every element, through which it can be updated or removed
+#[derive(Clone)]
+pub struct PriorityQueue<T, F = fn(&T, &T) -> Ordering> {
+    tree: BinaryTree<(Handle, T)>,
+    positions: HashMap<Handle, usize>,
+    next_handle: u64,
+    kind: Kind,
+    compare: F,
+}
+
+/// Identifies an element of a priority queue for as long as it is in the queue, handles are never reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(u64);
+
+impl<T: Ord> PriorityQueue<T> {
+    /// Creates an empty priority queue ordered by the natural order of its values
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value comes first
+    ///
+    pub fn new(kind: Kind) -> Self {
+        Self::with_comparator(kind, T::cmp)
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> PriorityQueue<T, F> {
+    /// Creates an empty priority queue ordered by a comparator
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Whether the smallest or the largest value according to the comparator comes first
+    /// * `compare` - Orders two values the same way as the comparators of slice::sort_by
+    ///
+    pub fn with_comparator(kind: Kind, compare: F) -> Self {
+        PriorityQueue {
+            tree: BinaryTree::default(),
+            positions: HashMap::new(),
+            next_handle: 0,
+            kind,
+            compare,
+        }
+    }
+
+    /// Returns the number of elements in the queue
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the queue holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns true if the element of the handle is still in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - A handle returned by push
+    ///
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.positions.contains_key(&handle)
+    }
+
+    /// Returns the value of an element, None if it is no longer in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - A handle returned by push
+    ///
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        let position = *self.positions.get(&handle)?;
+        Some(&self.tree[position].1)
+    }
+
+    /// Returns the element that comes first together with its handle, None if the queue is empty
+    pub fn peek(&self) -> Option<(Handle, &T)> {
+        self.tree.get(1).map(|(handle, value)| (*handle, value))
+    }
+
+    /// Adds an element in O(log n) and returns its handle
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value of the element
+    ///
+    pub fn push(&mut self, value: T) -> Handle {
+        let handle = Handle(self.next_handle);
+        self.next_handle += 1;
+        self.tree.add((handle, value));
+        self.positions.insert(handle, self.len());
+        self.sift_up(self.len());
+        handle
+    }
+
+    /// Removes the element that comes first in O(log n) and returns it together with its handle, None if the queue is empty
+    pub fn pop(&mut self) -> Option<(Handle, T)> {
+        let (handle, _) = self.tree.get(1)?;
+        let handle = *handle;
+        self.remove(handle).map(|value| (handle, value))
+    }
+
+    /// Changes the value of an element in O(log n), moving it up or down into place, and returns the previous value.
+    /// Returns None and leaves the queue unchanged if the element is no longer in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - A handle returned by push
+    /// * `value` - The new value of the element
+    ///
+    pub fn update(&mut self, handle: Handle, value: T) -> Option<T> {
+        let position = *self.positions.get(&handle)?;
+        let previous = std::mem::replace(&mut self.tree[position].1, value);
+        let position = self.sift_up(position);
+        self.sift_down(position);
+        Some(previous)
+    }
+
+    /// Removes an element in O(log n) and returns its value, None if it is no longer in the queue
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - A handle returned by push
+    ///
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let position = *self.positions.get(&handle)?;
+        // The last node takes the place of the removed one, which keeps the tree complete, and is then moved into place
+        self.swap(position, self.len());
+        let (handle, value) = self.tree.pop_last()?;
+        self.positions.remove(&handle);
+        if position <= self.len() {
+            let position = self.sift_up(position);
+            self.sift_down(position);
+        }
+        Some(value)
+    }
+}
+
+impl<T, F> BinaryTreeBehavior for PriorityQueue<T, F> {}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Sift for PriorityQueue<T, F> {
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn is_above(&self, a: usize, b: usize) -> bool {
+        self.kind
+            .is_above((self.compare)(&self.tree[a].1, &self.tree[b].1))
+    }
+
+    // Swaps two nodes and points the handles of both at their new positions
+    fn swap(&mut self, i: usize, j: usize) {
+        self.tree.swap(i, j);
+        for index in [i, j] {
+            self.positions.insert(self.tree[index].0, index);
+        }
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for PriorityQueue<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityQueue")
+            .field("tree", &self.tree)
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks that no node belongs above its parent and that every handle points at its node
+    fn assert_consistent<T, F: Fn(&T, &T) -> Ordering>(queue: &PriorityQueue<T, F>) {
+        assert_eq!(queue.positions.len(), queue.len());
+        for index in 1..=queue.len() {
+            assert_eq!(queue.positions[&queue.tree[index].0], index);
+            if index > 1 {
+                let parent = PriorityQueue::<T, F>::get_parent(index);
+                assert!(!queue.is_above(index, parent));
+            }
+        }
+    }
+
+    #[test]
+    fn should_pop_elements_in_order_with_their_handles() {
+        let mut queue = PriorityQueue::new(Kind::Min);
+        let handles: Vec<Handle> = [5, 3, 8, 1, 9, 2]
+            .into_iter()
+            .map(|v| queue.push(v))
+            .collect();
+        assert_consistent(&queue);
+        assert_eq!(queue.peek(), Some((handles[3], &1)));
+
+        let mut popped = Vec::new();
+        while let Some((handle, value)) = queue.pop() {
+            assert!(!queue.contains(handle));
+            popped.push(value);
+        }
+        assert_eq!(popped, [1, 2, 3, 5, 8, 9]);
+        assert_eq!(queue.get(handles[0]), None);
+    }
+
+    #[test]
+    fn should_update_and_remove_elements_by_handle() {
+        let mut queue = PriorityQueue::new(Kind::Max);
+        let handles: Vec<Handle> = (0..20).map(|v| queue.push(v * 10)).collect();
+
+        assert_eq!(queue.update(handles[3], 500), Some(30));
+        assert_eq!(queue.peek(), Some((handles[3], &500)));
+        assert_eq!(queue.update(handles[3], 5), Some(500));
+        assert_eq!(queue.get(handles[3]), Some(&5));
+        assert_consistent(&queue);
+
+        assert_eq!(queue.remove(handles[19]), Some(190));
+        assert_eq!(queue.remove(handles[7]), Some(70));
+        assert_eq!(queue.remove(handles[7]), None);
+        assert_eq!(queue.update(handles[7], 1), None);
+        assert_eq!(queue.len(), 18);
+        assert_consistent(&queue);
+
+        assert_eq!(queue.pop(), Some((handles[18], 180)));
+    }
+
+    #[test]
+    fn should_find_shortest_paths_with_decreasing_keys() {
+        // Edges of a small directed graph as (from, to, weight)
+        let edges = [
+            (0, 1, 7),
+            (0, 2, 9),
+            (0, 5, 14),
+            (1, 2, 10),
+            (1, 3, 15),
+            (2, 3, 11),
+            (2, 5, 2),
+            (3, 4, 6),
+            (5, 4, 9),
+        ];
+        let mut distances = [u32::MAX; 6];
+        distances[0] = 0;
+
+        // Every node is queued once with its distance and node, which is lowered through its handle
+        let mut queue = PriorityQueue::new(Kind::Min);
+        let handles: Vec<Handle> = (0..6)
+            .map(|node| queue.push((distances[node], node)))
+            .collect();
+        while let Some((_, (distance, node))) = queue.pop() {
+            for &(_, to, weight) in edges.iter().filter(|(from, _, _)| *from == node) {
+                if distance + weight < distances[to] {
+                    distances[to] = distance + weight;
+                    queue.update(handles[to], (distances[to], to)).unwrap();
+                }
+            }
+            assert_consistent(&queue);
+        }
+        assert_eq!(distances, [0, 7, 9, 20, 20, 11]);
+    }
+
+    #[test]
+    fn should_order_elements_with_a_comparator() {
+        let mut queue =
+            PriorityQueue::with_comparator(Kind::Min, |a: &(u32, &str), b: &(u32, &str)| {
+                a.0.cmp(&b.0)
+            });
+        let late = queue.push((30, "late"));
+        queue.push((10, "early"));
+        queue.update(late, (5, "late"));
+        assert_eq!(queue.pop().map(|(_, (_, name))| name), Some("late"));
+    }
+}
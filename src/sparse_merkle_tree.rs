@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::hasher::{Hash32, Hasher, Sha3_256};
+
+/// The depth of a sparse merkle tree, one level for every bit of a 256 bit key
+pub const SPARSE_DEPTH: usize = 256;
+
+// A sparse merkle tree has a leaf for every possible 256 bit key, which is far too many to allocate. Almost all of
+// them hold the empty (zero) value though, and every subtree that only holds empty leaves hashes to the same value
+// for its level. Those default hashes are computed once per level, so only the nodes that differ from the default of
+// their level have to be stored.
+//
+// A node is identified by its depth, counted from the root, and by the first `depth` bits of the keys below it.
+// The key bits are read from the most significant bit down, a 0 bit leads to the left child and a 1 bit to the right.
+/// A key value commitment over the whole 256 bit key space that only stores the non default nodes
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<H: Hasher = Sha3_256> {
+    nodes: HashMap<(usize, Hash32), Hash32>,
+    default_nodes: Vec<Hash32>,
+    len: usize,
+    hasher: PhantomData<H>,
+}
+
+/// A proof that a key holds a value in a sparse merkle tree, or that it is empty
+///
+/// The siblings are listed from the leaf level up to the level below the root, a None sibling is the default hash of its level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseMerkleProof<H: Hasher = Sha3_256> {
+    key: Hash32,
+    siblings: Vec<Option<Hash32>>,
+    hasher: PhantomData<H>,
+}
+
+// Returns the bit of the key at the given position, counting from the most significant bit
+fn bit(key: &Hash32, position: usize) -> bool {
+    (key.0[position / 8] >> (7 - position % 8)) & 1 == 1
+}
+
+// Returns the key with every bit from the given position on cleared, which identifies the node at that depth above the key
+fn prefix(key: &Hash32, depth: usize) -> Hash32 {
+    let mut prefix = *key;
+    for (i, byte) in prefix.0.iter_mut().enumerate() {
+        let bits_kept = depth.saturating_sub(8 * i).min(8);
+        *byte &= !(0xffu16 >> bits_kept) as u8;
+    }
+    prefix
+}
+
+// Returns the prefix of the sibling of the node at the given depth above the key
+fn sibling_prefix(key: &Hash32, depth: usize) -> Hash32 {
+    let mut sibling = prefix(key, depth);
+    let position = depth - 1;
+    sibling.0[position / 8] ^= 1 << (7 - position % 8);
+    sibling
+}
+
+// Returns the hashes of empty subtrees indexed by the depth of their root, the last one being the empty leaf
+fn default_nodes<H: Hasher>() -> Vec<Hash32> {
+    let mut default_nodes = vec![Hash32::default(); SPARSE_DEPTH + 1];
+    for depth in (0..SPARSE_DEPTH).rev() {
+        default_nodes[depth] = H::hash_pair(&default_nodes[depth + 1], &default_nodes[depth + 1]);
+    }
+    default_nodes
+}
+
+impl<H: Hasher> Default for SparseMerkleTree<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Hasher> SparseMerkleTree<H> {
+    /// Creates an empty sparse merkle tree where every key holds the empty (zero) value
+    pub fn new() -> Self {
+        SparseMerkleTree {
+            nodes: HashMap::new(),
+            default_nodes: default_nodes::<H>(),
+            len: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Returns the number of keys holding a non empty value
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if every key holds the empty value
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root hash of the tree
+    pub fn root(&self) -> Hash32 {
+        self.node(0, &Hash32::default())
+    }
+
+    /// Returns the value held by a key, None if the key is empty
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256 bit key
+    ///
+    pub fn get(&self, key: &Hash32) -> Option<&Hash32> {
+        self.nodes.get(&(SPARSE_DEPTH, *key))
+    }
+
+    /// Inserts or updates the value of a key and rehashes the path to the root, returning the previous value.
+    /// Inserting the empty (zero) value deletes the key
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256 bit key
+    /// * `value` - The hash of the value to commit to
+    ///
+    pub fn insert(&mut self, key: Hash32, value: Hash32) -> Option<Hash32> {
+        let previous = self.store(SPARSE_DEPTH, key, value);
+        match (previous.is_some(), value == Hash32::default()) {
+            (false, false) => self.len += 1,
+            (true, true) => self.len -= 1,
+            _ => {}
+        }
+
+        // Go all the way to the root and recalculate the hashes on the path of the key
+        for depth in (0..SPARSE_DEPTH).rev() {
+            let node_prefix = prefix(&key, depth);
+            let mut right_prefix = node_prefix;
+            right_prefix.0[depth / 8] |= 1 << (7 - depth % 8);
+            let hashed = H::hash_pair(
+                &self.node(depth + 1, &node_prefix),
+                &self.node(depth + 1, &right_prefix),
+            );
+            self.store(depth, node_prefix, hashed);
+        }
+        previous
+    }
+
+    /// Deletes the value of a key, returning it if the key was not empty
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256 bit key
+    ///
+    pub fn remove(&mut self, key: &Hash32) -> Option<Hash32> {
+        self.get(key)?;
+        self.insert(*key, Hash32::default())
+    }
+
+    /// Generates a proof for a key, which proves its value if it holds one or that it is empty otherwise
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The 256 bit key
+    ///
+    pub fn proof(&self, key: &Hash32) -> SparseMerkleProof<H> {
+        let siblings = (1..=SPARSE_DEPTH)
+            .rev()
+            .map(|depth| {
+                self.nodes
+                    .get(&(depth, sibling_prefix(key, depth)))
+                    .copied()
+            })
+            .collect();
+        SparseMerkleProof {
+            key: *key,
+            siblings,
+            hasher: PhantomData,
+        }
+    }
+
+    // Returns the hash of a node, falling back to the default hash of its level if it is not stored
+    fn node(&self, depth: usize, node_prefix: &Hash32) -> Hash32 {
+        self.nodes
+            .get(&(depth, *node_prefix))
+            .copied()
+            .unwrap_or(self.default_nodes[depth])
+    }
+
+    // Stores the hash of a node, or removes it when it equals the default hash of its level, returning the previous hash
+    fn store(&mut self, depth: usize, node_prefix: Hash32, hash: Hash32) -> Option<Hash32> {
+        if hash == self.default_nodes[depth] {
+            self.nodes.remove(&(depth, node_prefix))
+        } else {
+            self.nodes.insert((depth, node_prefix), hash)
+        }
+    }
+}
+
+impl<H: Hasher> SparseMerkleProof<H> {
+    /// Returns the key the proof was generated for
+    pub fn key(&self) -> &Hash32 {
+        &self.key
+    }
+
+    /// Returns the sibling hashes from the leaf level up, None being the default hash of the level
+    pub fn siblings(&self) -> &[Option<Hash32>] {
+        &self.siblings
+    }
+
+    /// Returns the root hash calculated from the value of the key and the sibling hashes of the proof
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value of the key, the empty (zero) value for a key that is not in the tree
+    ///
+    pub fn compute_root(&self, value: &Hash32) -> Hash32 {
+        let default_nodes = default_nodes::<H>();
+        let mut current = *value;
+        for (depth, sibling) in (1..=SPARSE_DEPTH).rev().zip(self.siblings.iter()) {
+            let sibling = sibling.unwrap_or(default_nodes[depth]);
+            current = if bit(&self.key, depth - 1) {
+                H::hash_pair(&sibling, &current)
+            } else {
+                H::hash_pair(&current, &sibling)
+            };
+        }
+        current
+    }
+
+    /// Returns true if the proof shows that the key holds the value in the tree with the given root
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value the key is expected to hold
+    /// * `root` - The root hash of the tree
+    ///
+    pub fn verify_inclusion(&self, value: &Hash32, root: &Hash32) -> bool {
+        self.siblings.len() == SPARSE_DEPTH
+            && *value != Hash32::default()
+            && self.compute_root(value) == *root
+    }
+
+    /// Returns true if the proof shows that the key is empty in the tree with the given root
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The root hash of the tree
+    ///
+    pub fn verify_non_inclusion(&self, root: &Hash32) -> bool {
+        self.siblings.len() == SPARSE_DEPTH && self.compute_root(&Hash32::default()) == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Hash32 {
+        Hash32([byte; 32])
+    }
+
+    // Computes the root of a tree holding the given leaves by splitting them on every bit, without any sparse storage
+    fn naive_root(leaves: &[(Hash32, Hash32)], depth: usize) -> Hash32 {
+        if leaves.is_empty() {
+            let mut hash = Hash32::default();
+            for _ in depth..SPARSE_DEPTH {
+                hash = Sha3_256::hash_pair(&hash, &hash);
+            }
+            return hash;
+        }
+        if depth == SPARSE_DEPTH {
+            return leaves[0].1;
+        }
+        let right: Vec<_> = leaves
+            .iter()
+            .filter(|(key, _)| bit(key, depth))
+            .copied()
+            .collect();
+        let left: Vec<_> = leaves
+            .iter()
+            .filter(|(key, _)| !bit(key, depth))
+            .copied()
+            .collect();
+        Sha3_256::hash_pair(
+            &naive_root(&left, depth + 1),
+            &naive_root(&right, depth + 1),
+        )
+    }
+
+    #[test]
+    fn should_clear_the_bits_below_the_depth_of_a_prefix() {
+        let key = key(0xff);
+        assert_eq!(prefix(&key, 0), Hash32::default());
+        assert_eq!(prefix(&key, 256), key);
+
+        let mut expected = Hash32::default();
+        expected.0[0] = 0xff;
+        expected.0[1] = 0xe0;
+        assert_eq!(prefix(&key, 11), expected);
+    }
+
+    #[test]
+    fn should_have_the_default_root_when_empty() {
+        let smt: SparseMerkleTree = SparseMerkleTree::new();
+        assert!(smt.is_empty());
+        assert_eq!(smt.root(), default_nodes::<Sha3_256>()[0]);
+    }
+
+    #[test]
+    fn should_match_the_root_of_the_full_tree() {
+        let mut smt: SparseMerkleTree = SparseMerkleTree::new();
+        let leaves = vec![
+            (key(0x00), key(0x11)),
+            (key(0x01), key(0x22)),
+            (key(0x80), key(0x33)),
+            (key(0xff), key(0x44)),
+        ];
+        for (key, value) in &leaves {
+            smt.insert(*key, *value);
+        }
+
+        assert_eq!(smt.len(), 4);
+        assert_eq!(smt.root(), naive_root(&leaves, 0));
+        assert_eq!(smt.get(&key(0x80)), Some(&key(0x33)));
+        assert_eq!(smt.get(&key(0x81)), None);
+    }
+
+    #[test]
+    fn should_not_depend_on_the_order_of_insertion() {
+        let mut forwards: SparseMerkleTree = SparseMerkleTree::new();
+        let mut backwards: SparseMerkleTree = SparseMerkleTree::new();
+        for byte in 0..20 {
+            forwards.insert(key(byte), key(byte + 100));
+        }
+        for byte in (0..20).rev() {
+            backwards.insert(key(byte), key(byte + 100));
+        }
+        assert_eq!(forwards.root(), backwards.root());
+    }
+
+    #[test]
+    fn should_update_and_delete_keys() {
+        let mut smt: SparseMerkleTree = SparseMerkleTree::new();
+        smt.insert(key(1), key(0x11));
+        let root_with_one_key = smt.root();
+
+        smt.insert(key(2), key(0x22));
+        assert_eq!(smt.insert(key(2), key(0x33)), Some(key(0x22)));
+        assert_eq!(smt.len(), 2);
+        assert_eq!(
+            smt.root(),
+            naive_root(&[(key(1), key(0x11)), (key(2), key(0x33))], 0)
+        );
+
+        assert_eq!(smt.remove(&key(2)), Some(key(0x33)));
+        assert_eq!(smt.remove(&key(2)), None);
+        assert_eq!(smt.len(), 1);
+        assert_eq!(smt.root(), root_with_one_key);
+
+        smt.remove(&key(1));
+        assert!(smt.is_empty());
+        assert!(
+            smt.nodes.is_empty(),
+            "Only non default nodes should be stored"
+        );
+    }
+
+    #[test]
+    fn should_prove_the_inclusion_of_a_key() {
+        let mut smt: SparseMerkleTree = SparseMerkleTree::new();
+        for byte in [3, 7, 200] {
+            smt.insert(key(byte), key(byte + 1));
+        }
+        let root = smt.root();
+        let proof = smt.proof(&key(7));
+
+        assert_eq!(proof.siblings().len(), SPARSE_DEPTH);
+        assert!(proof.verify_inclusion(&key(8), &root));
+        assert!(!proof.verify_inclusion(&key(9), &root));
+        assert!(!proof.verify_non_inclusion(&root));
+    }
+
+    #[test]
+    fn should_prove_the_non_inclusion_of_a_key() {
+        let mut smt: SparseMerkleTree = SparseMerkleTree::new();
+        for byte in [3, 7, 200] {
+            smt.insert(key(byte), key(byte + 1));
+        }
+        let root = smt.root();
+        let proof = smt.proof(&key(8));
+
+        assert!(proof.verify_non_inclusion(&root));
+        assert!(!proof.verify_inclusion(&key(9), &root));
+
+        // Most siblings of a sparse tree are default hashes, which the proof does not spell out
+        assert!(proof.siblings().iter().filter(|s| s.is_some()).count() <= 3);
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::hasher::{Hash32, Hasher, HashingMode, Sha3_256};
+use crate::merkle_tree::{Error, MerkleProof};
+
+// Nodes are immutable and shared between versions. Setting a leaf copies the nodes on the path from the root down to
+// that leaf and points the copies at the untouched siblings of the previous version, so every version costs O(depth)
+// new nodes and all of them stay valid for as long as a root refers to them. Subtrees that only hold filler leaves are
+// a single shared node per level, so even a deep tree starts out with one node per level.
+/// A persistent merkle tree of fixed depth in which every update creates a new version while older versions stay queryable
+#[derive(Debug, Clone)]
+pub struct VersionedMerkleTree<H: Hasher = Sha3_256> {
+    depth: u32,
+    mode: HashingMode,
+    roots: BTreeMap<Version, Arc<Node>>,
+    latest: Version,
+    hasher: PhantomData<H>,
+}
+
+/// A handle to one version of a versioned merkle tree, versions are numbered in the order they were created
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(u64);
+
+impl Version {
+    /// Returns the number of updates that led from the initial version to this one
+    pub fn number(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(Hash32),
+    Branch {
+        hash: Hash32,
+        left: Arc<Node>,
+        right: Arc<Node>,
+    },
+}
+
+impl Node {
+    fn hash(&self) -> &Hash32 {
+        match self {
+            Node::Leaf(hash) | Node::Branch { hash, .. } => hash,
+        }
+    }
+}
+
+impl<H: Hasher> VersionedMerkleTree<H> {
+    /// Creates a versioned merkle tree of depth whose leaves are all the filler leaf
+    ///
+    /// Panics if the depth is too large for the leaf indices, see `try_new` for a fallible version
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `filler` - The hash of every leaf of the initial version
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn new(depth: u32, filler: Hash32, mode: HashingMode) -> Self {
+        match Self::try_new(depth, filler, mode) {
+            Ok(tree) => tree,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a versioned merkle tree of depth whose leaves are all the filler leaf, returning an error if the depth is
+    /// too large for the leaf indices
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `filler` - The hash of every leaf of the initial version
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn try_new(depth: u32, filler: Hash32, mode: HashingMode) -> Result<Self, Error> {
+        Self::from_leaves(depth, [], filler, mode)
+    }
+
+    /// Creates a versioned merkle tree of depth from a list of leaves, the remaining leaf slots are padded with the filler leaf
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - An integer indicating the depth of the tree
+    /// * `leaves` - The hashes of the leaves of the initial version from left to right
+    /// * `filler` - The hash given to the leaf slots that are left over
+    /// * `mode` - Whether leaves and intermediate nodes are domain separated
+    ///
+    pub fn from_leaves<I: IntoIterator<Item = Hash32>>(
+        depth: u32,
+        leaves: I,
+        filler: Hash32,
+        mode: HashingMode,
+    ) -> Result<Self, Error> {
+        let max = usize::BITS - 1;
+        if depth > max {
+            return Err(Error::DepthTooLarge { depth, max });
+        }
+        let mut level: Vec<Arc<Node>> = leaves
+            .into_iter()
+            .map(|leaf| Arc::new(Node::Leaf(mode.leaf_node::<H>(&leaf))))
+            .collect();
+        let capacity = 1usize << depth;
+        if level.len() > capacity {
+            return Err(Error::LeafOutOfRange {
+                index: level.len() - 1,
+                leaf_count: capacity,
+            });
+        }
+
+        // Pair up the nodes level by level, the filled part one by one and everything right of it as one shared node
+        let mut filler_node = Arc::new(Node::Leaf(mode.leaf_node::<H>(&filler)));
+        for _ in 0..depth {
+            if level.len() % 2 == 1 {
+                level.push(filler_node.clone());
+            }
+            level = level
+                .chunks_exact(2)
+                .map(|pair| Self::branch(mode, pair[0].clone(), pair[1].clone()))
+                .collect();
+            filler_node = Self::branch(mode, filler_node.clone(), filler_node);
+        }
+        let root = level.pop().unwrap_or(filler_node);
+
+        let latest = Version(0);
+        Ok(VersionedMerkleTree {
+            depth,
+            mode,
+            roots: BTreeMap::from([(latest, root)]),
+            latest,
+            hasher: PhantomData,
+        })
+    }
+
+    fn branch(mode: HashingMode, left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+        Arc::new(Node::Branch {
+            hash: mode.parent_node::<H>(left.hash(), right.hash()),
+            left,
+            right,
+        })
+    }
+
+    /// Returns the depth of the tree
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the number of leaves of every version
+    pub fn leaf_count(&self) -> usize {
+        1 << self.depth
+    }
+
+    /// Returns the hashing mode of the tree
+    pub fn mode(&self) -> HashingMode {
+        self.mode
+    }
+
+    /// Returns the most recent version, which every update builds upon
+    pub fn latest(&self) -> Version {
+        self.latest
+    }
+
+    /// Returns the versions that have not been pruned, from the oldest to the latest
+    pub fn versions(&self) -> impl Iterator<Item = Version> + '_ {
+        self.roots.keys().copied()
+    }
+
+    // Returns the root node of a version that has not been pruned
+    fn root_node(&self, version: Version) -> Result<&Arc<Node>, Error> {
+        self.roots
+            .get(&version)
+            .ok_or(Error::UnknownVersion(version.0))
+    }
+
+    fn check_leaf_index(&self, leaf_index: usize) -> Result<(), Error> {
+        if leaf_index >= self.leaf_count() {
+            return Err(Error::LeafOutOfRange {
+                index: leaf_index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the root hash of the tree at a version
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - A version returned by this tree that has not been pruned
+    ///
+    pub fn root(&self, version: Version) -> Result<Hash32, Error> {
+        Ok(*self.root_node(version)?.hash())
+    }
+
+    /// Returns the value of the leaf node at leaf_index in a version, which is prefixed in the Rfc6962 mode
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - A version returned by this tree that has not been pruned
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn get(&self, version: Version, leaf_index: usize) -> Result<Hash32, Error> {
+        self.check_leaf_index(leaf_index)?;
+        let mut node = self.root_node(version)?;
+        for level in (0..self.depth).rev() {
+            node = Self::child(node, (leaf_index >> level) & 1 == 1).0;
+        }
+        Ok(*node.hash())
+    }
+
+    /// Generates the proof of a leaf against the root of a version
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - A version returned by this tree that has not been pruned
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    ///
+    pub fn proof(&self, version: Version, leaf_index: usize) -> Result<MerkleProof<H>, Error> {
+        self.check_leaf_index(leaf_index)?;
+        let mut node = self.root_node(version)?;
+        let mut siblings = Vec::with_capacity(self.depth as usize);
+        for level in (0..self.depth).rev() {
+            let (child, sibling) = Self::child(node, (leaf_index >> level) & 1 == 1);
+            siblings.push(*sibling.hash());
+            node = child;
+        }
+        // The path was walked from the root down, proofs list the siblings from the leaf level up
+        siblings.reverse();
+        MerkleProof::new(leaf_index, siblings, self.mode)
+    }
+
+    // Returns the child on the given side of a branch together with its sibling
+    fn child(node: &Arc<Node>, right: bool) -> (&Arc<Node>, &Arc<Node>) {
+        match node.as_ref() {
+            Node::Branch { left, right: r, .. } if right => (r, left),
+            Node::Branch { left, right: r, .. } => (left, r),
+            Node::Leaf(_) => unreachable!("Only the bottom level of the tree holds leaves"),
+        }
+    }
+
+    /// Sets a leaf on top of the latest version and returns the new version
+    ///
+    /// # Arguments
+    ///
+    /// * `leaf_index` - An integer indicating the the index of the leaf node among the group of leaves
+    /// * `leaf` - The new hash of the leaf
+    ///
+    pub fn set(&mut self, leaf_index: usize, leaf: Hash32) -> Result<Version, Error> {
+        self.set_many(&[(leaf_index, leaf)])
+    }
+
+    /// Sets a batch of leaves on top of the latest version and returns a single new version holding all of them.
+    /// Later updates of the same leaf win, and nothing is changed if any of the indices is out of range
+    ///
+    /// # Arguments
+    ///
+    /// * `updates` - Pairs of the index of a leaf among the group of leaves and its new hash
+    ///
+    pub fn set_many(&mut self, updates: &[(usize, Hash32)]) -> Result<Version, Error> {
+        for &(leaf_index, _) in updates {
+            self.check_leaf_index(leaf_index)?;
+        }
+
+        let mut root = self.root_node(self.latest)?.clone();
+        for &(leaf_index, leaf) in updates {
+            root = self.with_leaf(&root, leaf_index, leaf);
+        }
+
+        let version = Version(self.latest.0 + 1);
+        self.roots.insert(version, root);
+        self.latest = version;
+        Ok(version)
+    }
+
+    // Copies the path from the root down to a leaf with the new leaf at its end, sharing every other node with root
+    fn with_leaf(&self, root: &Arc<Node>, leaf_index: usize, leaf: Hash32) -> Arc<Node> {
+        let mut path = Vec::with_capacity(self.depth as usize);
+        let mut node = root;
+        for level in (0..self.depth).rev() {
+            let right = (leaf_index >> level) & 1 == 1;
+            let (child, sibling) = Self::child(node, right);
+            path.push((right, sibling.clone()));
+            node = child;
+        }
+
+        let mut node = Arc::new(Node::Leaf(self.mode.leaf_node::<H>(&leaf)));
+        for (right, sibling) in path.into_iter().rev() {
+            node = if right {
+                Self::branch(self.mode, sibling, node)
+            } else {
+                Self::branch(self.mode, node, sibling)
+            };
+        }
+        node
+    }
+
+    /// Drops every version older than the given one, returning how many were dropped. The latest version is always
+    /// kept, and nodes that are no longer part of a remaining version are freed
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The oldest version to keep
+    ///
+    pub fn prune_before(&mut self, version: Version) -> usize {
+        let kept = self.roots.split_off(&version.min(self.latest));
+        let pruned = self.roots.len();
+        self.roots = kept;
+        pruned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleTree;
+
+    const DEPTH: u32 = 3;
+
+    fn leaf(i: usize) -> Hash32 {
+        Hash32([i as u8 + 1; 32])
+    }
+
+    #[test]
+    fn should_match_a_mutable_tree_at_every_version() {
+        let mut tree: VersionedMerkleTree = VersionedMerkleTree::from_leaves(
+            DEPTH,
+            (0..5).map(leaf),
+            Hash32::default(),
+            HashingMode::Rfc6962,
+        )
+        .unwrap();
+        let mut mt: MerkleTree = MerkleTree::from_leaves_with_mode(
+            (0..8).map(|i| if i < 5 { leaf(i) } else { Hash32::default() }),
+            Hash32::default(),
+            HashingMode::Rfc6962,
+        )
+        .unwrap();
+
+        let mut history = vec![(tree.latest(), mt.clone())];
+        for (step, index) in [3, 7, 3, 0].into_iter().enumerate() {
+            let version = tree.set(index, leaf(10 + step)).unwrap();
+            mt.set_many(&[(index, leaf(10 + step))]).unwrap();
+            assert_eq!(version.number(), step as u64 + 1);
+            history.push((version, mt.clone()));
+        }
+
+        for (version, mt) in &history {
+            assert_eq!(tree.root(*version).unwrap(), *mt.root_hash());
+            for index in 0..tree.leaf_count() {
+                assert_eq!(tree.get(*version, index).unwrap(), *mt.get(8 + index));
+                assert_eq!(
+                    tree.proof(*version, index).unwrap(),
+                    mt.merkle_proof(index).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_share_the_nodes_an_update_does_not_touch() {
+        let mut tree: VersionedMerkleTree =
+            VersionedMerkleTree::new(DEPTH, Hash32::default(), HashingMode::Plain);
+        let initial = tree.latest();
+        let updated = tree.set(0, leaf(0)).unwrap();
+
+        let (old, new) = (
+            tree.root_node(initial).unwrap(),
+            tree.root_node(updated).unwrap(),
+        );
+        assert!(Arc::ptr_eq(
+            VersionedMerkleTree::<Sha3_256>::child(old, true).0,
+            VersionedMerkleTree::<Sha3_256>::child(new, true).0
+        ));
+        assert!(!Arc::ptr_eq(
+            VersionedMerkleTree::<Sha3_256>::child(old, false).0,
+            VersionedMerkleTree::<Sha3_256>::child(new, false).0
+        ));
+    }
+
+    #[test]
+    fn should_create_a_single_version_for_a_batch() {
+        let mut tree: VersionedMerkleTree =
+            VersionedMerkleTree::new(DEPTH, Hash32::default(), HashingMode::Plain);
+        let mut one_by_one = tree.clone();
+        for index in [1, 6, 1] {
+            one_by_one.set(index, leaf(index)).unwrap();
+        }
+
+        let version = tree
+            .set_many(&[(1, leaf(1)), (6, leaf(6)), (1, leaf(1))])
+            .unwrap();
+        assert_eq!(version.number(), 1);
+        assert_eq!(tree.root(version), one_by_one.root(one_by_one.latest()));
+
+        assert_eq!(
+            tree.set_many(&[(2, leaf(2)), (8, leaf(8))]),
+            Err(Error::LeafOutOfRange {
+                index: 8,
+                leaf_count: 8
+            })
+        );
+        assert_eq!(tree.latest(), version);
+    }
+
+    #[test]
+    fn should_prune_old_versions_but_keep_the_latest() {
+        let mut tree: VersionedMerkleTree =
+            VersionedMerkleTree::new(DEPTH, Hash32::default(), HashingMode::Plain);
+        let versions: Vec<Version> = (0..4).map(|i| tree.set(i, leaf(i)).unwrap()).collect();
+
+        assert_eq!(tree.prune_before(versions[2]), 3);
+        assert_eq!(tree.versions().collect::<Vec<_>>(), &versions[2..]);
+        assert_eq!(tree.root(versions[1]), Err(Error::UnknownVersion(2)));
+        assert_eq!(
+            tree.proof(versions[1], 0).unwrap_err(),
+            Error::UnknownVersion(2)
+        );
+        assert!(tree.root(versions[2]).is_ok());
+
+        assert_eq!(tree.prune_before(Version(100)), 1);
+        assert_eq!(tree.versions().collect::<Vec<_>>(), [tree.latest()]);
+    }
+}